@@ -0,0 +1,51 @@
+// Compares the case-folding `search_case_insensitive` with the previous
+// implementation, which lowercased a fresh copy of every line.
+//
+// Run with `cargo bench --bench case_insensitive`.
+
+mod common;
+
+use std::hint::black_box;
+
+use common::bench;
+use minigrep::search_case_insensitive;
+
+// The implementation `search_case_insensitive` had before case folding.
+fn search_lowercase<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
+    let query = query.to_lowercase();
+    contents
+        .lines()
+        .filter(|line| line.to_lowercase().contains(&query))
+        .collect()
+}
+
+fn corpus() -> String {
+    let poem = include_str!("../data/poem.txt");
+    let mut contents = String::new();
+    for i in 0..2_000 {
+        contents.push_str(poem);
+        contents.push_str(&format!(
+            "Line {i}: Straße, ΣΟΦΙΑ, Trust me, {}\n",
+            "x".repeat(i % 80)
+        ));
+    }
+    contents
+}
+
+fn main() {
+    let contents = corpus();
+    println!(
+        "{} bytes, {} lines",
+        contents.len(),
+        contents.lines().count()
+    );
+
+    for query in ["frog", "NOBODY", "straße"] {
+        bench(&format!("to_lowercase   {query:?}"), contents.len(), || {
+            search_lowercase(black_box(query), black_box(&contents)).len()
+        });
+        bench(&format!("case folding   {query:?}"), contents.len(), || {
+            search_case_insensitive(black_box(query), black_box(&contents)).len()
+        });
+    }
+}
@@ -0,0 +1,23 @@
+// The timing loop the benches share.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+/// Runs `f` until about a second has passed and reports the mean time per
+/// run, and how many bytes of `len` that gets through per second.
+pub fn bench<F: FnMut() -> usize>(name: &str, len: usize, mut f: F) {
+    for _ in 0..3 {
+        black_box(f());
+    }
+    let mut runs = 0u32;
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(1) {
+        black_box(f());
+        runs += 1;
+    }
+    let per_run = start.elapsed() / runs;
+    println!(
+        "{name:<32} {per_run:>12.1?}/iter {:>9.1} MiB/s ({runs} runs)",
+        len as f64 / per_run.as_secs_f64() / (1024.0 * 1024.0)
+    );
+}
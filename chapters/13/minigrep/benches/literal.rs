@@ -0,0 +1,83 @@
+// Compares the whole-buffer literal search with the previous line-by-line
+// `contains`, both on a string in memory (`search`) and streaming through a
+// `Searcher`, which reads its input in chunks.
+//
+// Run with `cargo bench --bench literal`.
+
+mod common;
+
+use std::hint::black_box;
+use std::io;
+
+use common::bench;
+use minigrep::{Config, Searcher, Sink, SinkMatch};
+
+// The implementation `search` had before scanning whole buffers.
+fn search_lines<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
+    contents
+        .lines()
+        .filter(|line| line.contains(query))
+        .collect()
+}
+
+// Counts selected lines and does nothing else.
+struct Count(usize);
+
+impl Sink for Count {
+    fn matched(&mut self, _line: &SinkMatch<'_>) -> io::Result<bool> {
+        self.0 += 1;
+        Ok(true)
+    }
+}
+
+fn corpus() -> String {
+    let poem = include_str!("../data/poem.txt");
+    let mut contents = String::new();
+    for i in 0..20_000 {
+        contents.push_str(poem);
+        contents.push_str(&format!(
+            "Line {i}: the quick brown fox jumps over the lazy dog, {}\n",
+            "x".repeat(i % 80)
+        ));
+    }
+    contents
+}
+
+fn main() {
+    let contents = corpus();
+    println!(
+        "{} bytes, {} lines",
+        contents.len(),
+        contents.lines().count()
+    );
+
+    // Rare, common, absent, a single byte and a long periodic query.
+    for query in [
+        "frog",
+        "the",
+        "zebra crossing",
+        "!",
+        "xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxy",
+    ] {
+        let label = format!("{:?}", query.chars().take(16).collect::<String>());
+        bench(&format!("lines      {label}"), contents.len(), || {
+            search_lines(black_box(query), black_box(&contents)).len()
+        });
+        bench(&format!("buffer     {label}"), contents.len(), || {
+            minigrep::search(black_box(query), black_box(&contents)).len()
+        });
+
+        let searcher = Searcher::new(&Config {
+            patterns: vec![query.to_string()],
+            ..Config::default()
+        })
+        .unwrap();
+        bench(&format!("searcher   {label}"), contents.len(), || {
+            let mut count = Count(0);
+            searcher
+                .search_reader("corpus", black_box(contents.as_bytes()), &mut count)
+                .unwrap();
+            count.0
+        });
+    }
+}
@@ -0,0 +1,245 @@
+// Aho–Corasick automaton for searching many literal patterns at once.
+//
+// All patterns go into one trie whose nodes get failure links (the longest
+// proper suffix of a node that is also in the trie), so the haystack is read
+// a single time no matter how many patterns there are: the cost is linear
+// in the length of the line plus the number of matches reported.
+//
+// With `ignore_case` the trie holds case-folded patterns and each haystack
+// char is folded (see `fold`) as it is fed in.
+
+use std::collections::HashMap;
+
+use crate::fold::fold;
+
+#[derive(Debug, Default)]
+struct Node {
+    next: HashMap<char, usize>,
+    fail: usize,
+    // Patterns (by length in chars) that end here, including those reached
+    // through failure links.
+    outputs: Vec<usize>,
+}
+
+#[derive(Debug)]
+pub struct AhoCorasick {
+    nodes: Vec<Node>,
+    ignore_case: bool,
+    // Longest pattern, in (folded) chars.
+    max_len: usize,
+    has_empty: bool,
+}
+
+impl AhoCorasick {
+    pub fn new<S: AsRef<str>>(patterns: &[S], ignore_case: bool) -> AhoCorasick {
+        let mut nodes = vec![Node::default()];
+        let mut max_len = 0;
+        let mut has_empty = false;
+
+        for pattern in patterns {
+            let chars: Vec<char> = if ignore_case {
+                pattern.as_ref().chars().flat_map(fold).collect()
+            } else {
+                pattern.as_ref().chars().collect()
+            };
+            has_empty |= chars.is_empty();
+            max_len = max_len.max(chars.len());
+
+            let mut state = 0;
+            for &c in &chars {
+                state = match nodes[state].next.get(&c) {
+                    Some(&next) => next,
+                    None => {
+                        nodes.push(Node::default());
+                        let next = nodes.len() - 1;
+                        nodes[state].next.insert(c, next);
+                        next
+                    }
+                };
+            }
+            if !chars.is_empty() && !nodes[state].outputs.contains(&chars.len()) {
+                nodes[state].outputs.push(chars.len());
+            }
+        }
+
+        // Breadth-first, so a node's failure target is finished before it.
+        let mut queue: Vec<usize> = nodes[0].next.values().copied().collect();
+        let mut head = 0;
+        while head < queue.len() {
+            let state = queue[head];
+            head += 1;
+            let edges: Vec<(char, usize)> =
+                nodes[state].next.iter().map(|(&c, &n)| (c, n)).collect();
+            for (c, child) in edges {
+                let mut fail = nodes[state].fail;
+                let target = loop {
+                    if let Some(&next) = nodes[fail].next.get(&c) {
+                        break next;
+                    }
+                    if fail == 0 {
+                        break 0;
+                    }
+                    fail = nodes[fail].fail;
+                };
+                nodes[child].fail = target;
+                let inherited = nodes[target].outputs.clone();
+                nodes[child].outputs.extend(inherited);
+                queue.push(child);
+            }
+        }
+
+        AhoCorasick {
+            nodes,
+            ignore_case,
+            max_len,
+            has_empty,
+        }
+    }
+
+    /// Leftmost-longest match at or after byte offset `start`.
+    pub fn find_at(&self, text: &str, start: usize) -> Option<(usize, usize)> {
+        self.find_at_where(text, start, |_, _| true)
+    }
+
+    /// Like `find_at`, but only considers matches for which `accept` holds.
+    /// Every occurrence of every pattern is a candidate (not just the
+    /// leftmost-longest one), so e.g. a whole-word check can still find
+    /// "duct" in "product duct".
+    pub fn find_at_where<F>(&self, text: &str, start: usize, accept: F) -> Option<(usize, usize)>
+    where
+        F: Fn(usize, usize) -> bool,
+    {
+        let mut state = 0;
+        let mut best: Option<(usize, usize)> = None;
+        // Chars fed in since the start of `best`. Once that exceeds the
+        // longest pattern, no later match can begin at or before it.
+        let mut since_best = 0;
+
+        let consider = |best: &mut Option<(usize, usize)>, from: usize, to: usize| {
+            let better = match *best {
+                None => true,
+                Some((s, e)) => from < s || (from == s && to > e),
+            };
+            if better && accept(from, to) {
+                *best = Some((from, to));
+                return true;
+            }
+            false
+        };
+
+        if self.has_empty && consider(&mut best, start, start) {
+            since_best = 0;
+        }
+        for (i, c) in text[start..].char_indices() {
+            if best.is_some() && since_best >= self.max_len {
+                break;
+            }
+            let end = start + i + c.len_utf8();
+            let mut fed = 0;
+            if self.ignore_case {
+                for c in fold(c) {
+                    state = self.step(state, c);
+                    fed += 1;
+                }
+            } else {
+                state = self.step(state, c);
+                fed = 1;
+            }
+            since_best += fed;
+
+            for &len in &self.nodes[state].outputs {
+                let Some(from) = self.match_start(text, end, len) else {
+                    continue;
+                };
+                if from >= start && consider(&mut best, from, end) {
+                    since_best = self.folded_len(&text[from..end]);
+                }
+            }
+            if self.has_empty && consider(&mut best, end, end) {
+                since_best = 0;
+            }
+        }
+
+        best
+    }
+
+    fn step(&self, mut state: usize, c: char) -> usize {
+        loop {
+            if let Some(&next) = self.nodes[state].next.get(&c) {
+                return next;
+            }
+            if state == 0 {
+                return 0;
+            }
+            state = self.nodes[state].fail;
+        }
+    }
+
+    // Byte offset where a match of `len` (folded) chars ending at `end`
+    // begins, or None if it would begin halfway through a folded char.
+    fn match_start(&self, text: &str, end: usize, len: usize) -> Option<usize> {
+        let mut need = len;
+        for (i, c) in text[..end].char_indices().rev() {
+            let n = if self.ignore_case { fold(c).count() } else { 1 };
+            if n > need {
+                return None;
+            }
+            need -= n;
+            if need == 0 {
+                return Some(i);
+            }
+        }
+        None
+    }
+
+    fn folded_len(&self, text: &str) -> usize {
+        if self.ignore_case {
+            text.chars().map(|c| fold(c).count()).sum()
+        } else {
+            text.chars().count()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_leftmost_longest() {
+        let ac = AhoCorasick::new(&["he", "she", "his", "hers"], false);
+        assert_eq!(Some((1, 4)), ac.find_at("ushers", 0));
+        assert_eq!(Some((2, 6)), ac.find_at("ushers", 2));
+        assert_eq!(None, ac.find_at("usher", 3));
+
+        let ac = AhoCorasick::new(&["abcd", "bc"], false);
+        assert_eq!(Some((0, 4)), ac.find_at("abcd", 0));
+        assert_eq!(Some((1, 3)), ac.find_at("abce", 0));
+    }
+
+    #[test]
+    fn many_patterns() {
+        let patterns: Vec<String> = (0..500).map(|i| format!("ident_{i}")).collect();
+        let ac = AhoCorasick::new(&patterns, false);
+        assert_eq!(Some((4, 13)), ac.find_at("let ident_499 = 1;", 0));
+        assert_eq!(None, ac.find_at("let ident_x = 1;", 0));
+    }
+
+    #[test]
+    fn ignore_case() {
+        let ac = AhoCorasick::new(&["STRASSE", "rust"], true);
+        assert_eq!(Some((5, 12)), ac.find_at("Hauptstraße", 0));
+        assert_eq!(Some((1, 5)), ac.find_at("TRUST", 0));
+    }
+
+    #[test]
+    fn accept_filter() {
+        let ac = AhoCorasick::new(&["duct"], false);
+        let text = "product duct";
+        let word = |s: usize, e: usize| {
+            !text[..s].ends_with(char::is_alphanumeric)
+                && !text[e..].starts_with(char::is_alphanumeric)
+        };
+        assert_eq!(Some((8, 12)), ac.find_at_where(text, 0, word));
+    }
+}
@@ -0,0 +1,982 @@
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+
+use crate::glob::GlobError;
+use crate::rcfile::RcFile;
+use crate::types;
+use crate::walk::Filter;
+
+pub const USAGE: &str = "\
+Usage: minigrep [OPTION]... QUERY [FILE]...
+  or:  minigrep [OPTION]... -e QUERY... [FILE]...
+Search for QUERY in each FILE. Directories are searched recursively.
+With no FILE, or when FILE is -, read standard input.
+
+Options:
+  -e, --regexp=QUERY        search for QUERY; may be given more than once
+  -f, --file=FILE           search for each line of FILE
+  -E, --regex               treat each QUERY as a regular expression
+  -w, --word-regexp         match only whole words
+  -x, --line-regexp         match only whole lines
+  -U, --multiline           let matches span lines: \\n in a QUERY matches a
+                            line break, and every line a match touches is
+                            printed
+      --fuzzy=K             allow up to K typos (edits) in each match, and
+                            list the closest lines of all FILEs first
+  -i, --ignore-case         ignore case distinctions (or set IGNORE_CASE)
+  -S, --smart-case          ignore case unless a QUERY has uppercase letters
+  -v, --invert-match        select non-matching lines
+  -n, --line-number         print the line number with each line
+  -c, --count               print only a count of selected lines per file
+      --count-matches       print only a count of matches per file
+  -l, --files-with-matches  print only the names of files with selected lines
+  -z, --decompress          treat every input as gzip (gzip files are otherwise
+                            recognised by their first bytes)
+      --follow              keep searching each FILE as it grows, like
+                            tail -f, through truncation and log rotation
+  -t, --type=NAME           only search files of type NAME (rust, py, js, c,
+                            markdown, ...) when walking directories
+  -T, --type-not=NAME       don't search files of type NAME
+  -g, --glob=GLOB           only search files matching GLOB when walking
+                            directories, or skip them if GLOB starts with !;
+                            the last matching GLOB wins
+  -A, --after-context=NUM   print NUM lines of trailing context
+  -B, --before-context=NUM  print NUM lines of leading context
+  -C, --context=NUM         print NUM lines of context on both sides
+  -j, --threads=NUM         search NUM files at once (default: one per CPU)
+      --color=WHEN          highlight matches: always, never or auto (default)
+      --json                print results as JSON Lines
+      --stats               print totals (matches, files, bytes, time) at the
+                            end
+      --replace=TEXT        print lines with each match replaced by TEXT; with
+                            -E, TEXT may use $1, ${name}, $0 and $$
+      --in-place            with --replace, rewrite the files instead
+      --dry-run             with --in-place, print a diff instead of writing
+      --no-config           don't read the config file
+  -h, --help                display this help and exit
+  -V, --version             display version information and exit
+
+Short options can be combined (-inv). Everything after `--` is an operand.
+An option without an argument can be turned off with --no-NAME, such as
+--no-line-number, which is how to undo one the config file sets. Of the
+options that take one, --type, --type-not and --glob add to what was given
+before, which --no-type, --no-type-not and --no-glob forget, as do
+--no-fuzzy and --no-replace; the others keep the last value given.
+
+Default options, file types (`rust = *.rs`) and globs to ignore are read from
+$MINIGREP_CONFIG, or else ~/.minigreprc; options on the command line take
+precedence over the file's.";
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Debug, Default)]
+pub struct Config {
+    // A line is selected if any of these matches.
+    pub patterns: Vec<String>,
+    pub paths: Vec<String>,
+    pub ignore_case: bool,
+    pub smart_case: bool,
+    pub regex: bool,
+    // --fuzzy: the most edits (Levenshtein distance) a match may be off by.
+    pub fuzzy: Option<usize>,
+    pub word_regexp: bool,
+    pub line_regexp: bool,
+    // -U: patterns are matched against the whole input rather than line by
+    // line, so they can contain line breaks.
+    pub multiline: bool,
+    pub invert_match: bool,
+    pub line_number: bool,
+    pub count: bool,
+    // Like -c, but counting every match rather than the lines with any.
+    pub count_matches: bool,
+    pub files_with_matches: bool,
+    pub before_context: usize,
+    pub after_context: usize,
+    // 0 picks one thread per CPU.
+    pub threads: usize,
+    pub color: ColorChoice,
+    pub json: bool,
+    pub stats: bool,
+    pub decompress: bool,
+    // --follow: search the files, then whatever gets added to them.
+    pub follow: bool,
+    pub replace: Option<String>,
+    pub in_place: bool,
+    pub dry_run: bool,
+    // -g: globs for files to search, or with `!` to skip.
+    pub globs: Vec<String>,
+    // --type and --type-not: only walk into files of these types, and not
+    // into those of these...
+    pub types: Vec<String>,
+    pub types_not: Vec<String>,
+    // ...which are built in, or defined by the config file as lists of
+    // file name globs.
+    pub file_types: Vec<(String, Vec<String>)>,
+    // Gitignore-style rules from the config file for files to skip when
+    // walking directories.
+    pub ignore: Vec<String>,
+    pub no_config: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    // Color only when stdout is a terminal.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+// Short options and the long option each one stands for.
+const SHORT_OPTIONS: [(char, &str); 22] = [
+    ('e', "regexp"),
+    ('f', "file"),
+    ('E', "regex"),
+    ('w', "word-regexp"),
+    ('x', "line-regexp"),
+    ('U', "multiline"),
+    ('i', "ignore-case"),
+    ('S', "smart-case"),
+    ('v', "invert-match"),
+    ('n', "line-number"),
+    ('c', "count"),
+    ('l', "files-with-matches"),
+    ('z', "decompress"),
+    ('t', "type"),
+    ('T', "type-not"),
+    ('g', "glob"),
+    ('A', "after-context"),
+    ('B', "before-context"),
+    ('C', "context"),
+    ('j', "threads"),
+    ('h', "help"),
+    ('V', "version"),
+];
+
+// Long options that take an argument (`--name=VALUE`, `--name VALUE`,
+// `-XVALUE` or `-X VALUE`).
+const VALUE_OPTIONS: [&str; 12] = [
+    "regexp",
+    "file",
+    "after-context",
+    "before-context",
+    "context",
+    "threads",
+    "color",
+    "replace",
+    "fuzzy",
+    "type",
+    "type-not",
+    "glob",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    MissingQuery,
+    UnknownOption(String),
+    MissingValue(String),
+    UnexpectedValue(String),
+    InvalidValue { option: String, value: String },
+    PatternFile { path: String, reason: String },
+    // An option that only makes sense together with something else.
+    Requires { option: String, needs: String },
+    Conflict(String, String),
+    UnknownType(String),
+    Glob(GlobError),
+    RcFile { path: String, reason: String },
+    // Not failures as such: the caller should print USAGE or VERSION and
+    // exit successfully.
+    HelpRequested,
+    VersionRequested,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::MissingQuery => write!(f, "Didn't get a query string"),
+            ConfigError::UnknownOption(option) => write!(f, "Unknown option '{option}'"),
+            ConfigError::MissingValue(option) => {
+                write!(f, "Option '{option}' requires an argument")
+            }
+            ConfigError::UnexpectedValue(option) => {
+                write!(f, "Option '{option}' doesn't allow an argument")
+            }
+            ConfigError::InvalidValue { option, value } => {
+                write!(f, "Invalid argument '{value}' for '{option}'")
+            }
+            ConfigError::PatternFile { path, reason } => {
+                write!(f, "Can't read patterns from '{path}': {reason}")
+            }
+            ConfigError::Requires { option, needs } => {
+                write!(f, "Option '{option}' requires {needs}")
+            }
+            ConfigError::Conflict(option, other) => {
+                write!(f, "Options '{option}' and '{other}' can't be used together")
+            }
+            ConfigError::UnknownType(name) => write!(f, "Unknown file type '{name}'"),
+            ConfigError::Glob(err) => write!(f, "{err}"),
+            ConfigError::RcFile { path, reason } => {
+                write!(f, "Invalid config file '{path}': {reason}")
+            }
+            ConfigError::HelpRequested => write!(f, "{USAGE}"),
+            ConfigError::VersionRequested => write!(f, "minigrep {VERSION}"),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+impl From<GlobError> for ConfigError {
+    fn from(err: GlobError) -> ConfigError {
+        ConfigError::Glob(err)
+    }
+}
+
+impl Config {
+    pub fn build(args: impl Iterator<Item = String>) -> Result<Config, ConfigError> {
+        Config::build_with(args, RcFile::load)
+    }
+
+    // `load_rc` finds the config file; it isn't called with --no-config.
+    fn build_with(
+        mut args: impl Iterator<Item = String>,
+        load_rc: impl FnOnce() -> Result<Option<RcFile>, ConfigError>,
+    ) -> Result<Config, ConfigError> {
+        args.next(); // program name (after release, binary name) is useless
+        let args: Vec<String> = args.collect();
+
+        // -f files are only read the first time through.
+        let mut pattern_files = HashMap::new();
+        let mut config = Config::parse(None, &args, &mut pattern_files)?;
+        if !config.no_config {
+            if let Some(rc) = load_rc()? {
+                // Again with the file's flags in front, so that the command
+                // line gets the last word.
+                config = Config::parse(Some(&rc), &args, &mut pattern_files)?;
+            }
+        }
+        // Only what the file and the command line add up to has to make
+        // sense, not the command line alone.
+        let config = Config::check(config)?;
+        // Unknown types and broken globs are usage errors.
+        config.walk_filter()?;
+        Ok(config)
+    }
+
+    /// What files found by walking directories have to pass: the config
+    /// file's ignore rules, -g globs and file types.
+    pub fn walk_filter(&self) -> Result<Filter, ConfigError> {
+        let mut filter = Filter::default();
+        for rule in &self.ignore {
+            filter.ignore(rule)?;
+        }
+        for glob in &self.globs {
+            filter.glob(glob)?;
+        }
+        for (names, selected) in [(&self.types, true), (&self.types_not, false)] {
+            for name in names {
+                let globs = types::globs(&self.file_types, name)
+                    .ok_or_else(|| ConfigError::UnknownType(name.clone()))?;
+                for glob in globs {
+                    if selected {
+                        filter.include(glob)?;
+                    } else {
+                        filter.exclude(glob)?;
+                    }
+                }
+            }
+        }
+        Ok(filter)
+    }
+
+    // `pattern_files` holds the contents of the -f files read so far.
+    fn parse(
+        rc: Option<&RcFile>,
+        args: &[String],
+        pattern_files: &mut HashMap<String, String>,
+    ) -> Result<Config, ConfigError> {
+        let mut config = Config::default();
+        if let Some(rc) = rc {
+            let error = |reason: String| ConfigError::RcFile {
+                path: rc.path.clone(),
+                reason,
+            };
+            let mut operands = Vec::new();
+            let explicit_patterns = config
+                .parse_options(rc.flags.iter().cloned(), &mut operands, pattern_files)
+                .map_err(|err| match err {
+                    ConfigError::HelpRequested | ConfigError::VersionRequested => {
+                        error("'--help' and '--version' can't be set there".to_string())
+                    }
+                    err => error(err.to_string()),
+                })?;
+            if explicit_patterns || !operands.is_empty() || config.no_config {
+                return Err(error(
+                    "only options can be set there, not patterns".to_string(),
+                ));
+            }
+            config.file_types = rc.types.clone();
+            config.ignore = rc.ignore.clone();
+        }
+
+        let mut operands = Vec::new();
+        let explicit_patterns =
+            config.parse_options(args.iter().cloned(), &mut operands, pattern_files)?;
+
+        let mut operands = operands.into_iter();
+        // With -e or -f the patterns are already known and every operand is
+        // a file.
+        if !explicit_patterns {
+            let query = operands.next().ok_or(ConfigError::MissingQuery)?;
+            config.patterns.push(query);
+        }
+        config.paths = operands.collect();
+        if config.paths.is_empty() {
+            config.paths.push("-".to_string());
+        }
+        Ok(config)
+    }
+
+    // Rejects options that are missing what they need or can't go together,
+    // and settles what depends on several of them.
+    fn check(mut config: Config) -> Result<Config, ConfigError> {
+        let requires = |option: &str, needs: &str| ConfigError::Requires {
+            option: option.to_string(),
+            needs: needs.to_string(),
+        };
+        if config.fuzzy.is_some() {
+            let conflict = [
+                (config.regex, "--regex"),
+                (config.word_regexp, "--word-regexp"),
+                (config.line_regexp, "--line-regexp"),
+                (config.multiline, "--multiline"),
+            ]
+            .into_iter()
+            .find(|&(set, _)| set);
+            if let Some((_, other)) = conflict {
+                return Err(ConfigError::Conflict(
+                    "--fuzzy".to_string(),
+                    other.to_string(),
+                ));
+            }
+        }
+        if config.dry_run && !config.in_place {
+            return Err(requires("--dry-run", "'--in-place'"));
+        }
+        if config.in_place && config.replace.is_none() {
+            return Err(requires("--in-place", "'--replace'"));
+        }
+        if config.in_place && config.paths.iter().any(|path| path == "-") {
+            return Err(requires("--in-place", "FILE operands"));
+        }
+        if config.follow {
+            if config.paths.iter().any(|path| path == "-") {
+                return Err(requires("--follow", "FILE operands"));
+            }
+            // Followed files are never done, so there's no end to sum up
+            // at, they're read as they are, and a match can't wait for
+            // lines that may never come.
+            let conflict = [
+                (config.count, "--count"),
+                (config.count_matches, "--count-matches"),
+                (config.files_with_matches, "--files-with-matches"),
+                (config.stats, "--stats"),
+                (config.in_place, "--in-place"),
+                (config.decompress, "--decompress"),
+                (config.multiline, "--multiline"),
+            ]
+            .into_iter()
+            .find(|&(set, _)| set);
+            if let Some((_, other)) = conflict {
+                return Err(ConfigError::Conflict(
+                    "--follow".to_string(),
+                    other.to_string(),
+                ));
+            }
+        }
+        if config.in_place {
+            // Files are edited a line at a time, and only the lines that
+            // match are replaced.
+            let conflict = [
+                (config.multiline, "--multiline"),
+                (config.invert_match, "--invert-match"),
+            ]
+            .into_iter()
+            .find(|&(set, _)| set);
+            if let Some((_, other)) = conflict {
+                return Err(ConfigError::Conflict(
+                    "--in-place".to_string(),
+                    other.to_string(),
+                ));
+            }
+        }
+
+        // The regex syntax already has `\n`; plain patterns get it with -U.
+        if config.multiline && !config.regex {
+            for pattern in &mut config.patterns {
+                *pattern = pattern.replace("\\n", "\n");
+            }
+        }
+
+        // An explicit -i wins, then --smart-case, then the environment
+        // variable.
+        if !config.ignore_case {
+            config.ignore_case = if config.smart_case {
+                !config
+                    .patterns
+                    .iter()
+                    .any(|pattern| pattern.chars().any(char::is_uppercase))
+            } else {
+                env::var("IGNORE_CASE").is_ok()
+            };
+        }
+
+        Ok(config)
+    }
+
+    // Applies the options in `args`, collecting operands in `operands`.
+    // Returns whether patterns were given with -e or -f.
+    fn parse_options(
+        &mut self,
+        mut args: impl Iterator<Item = String>,
+        operands: &mut Vec<String>,
+        pattern_files: &mut HashMap<String, String>,
+    ) -> Result<bool, ConfigError> {
+        let mut options_done = false;
+        let mut explicit_patterns = false;
+
+        // Options and operands may be mixed freely, like GNU grep; a lone
+        // `-` is an operand and `--` ends option parsing.
+        while let Some(arg) = args.next() {
+            if options_done || arg == "-" || !arg.starts_with('-') {
+                operands.push(arg);
+            } else if arg == "--" {
+                options_done = true;
+            } else if let Some(long) = arg.strip_prefix("--") {
+                let (name, value) = match long.split_once('=') {
+                    Some((name, value)) => (name, Some(value.to_string())),
+                    None => (long, None),
+                };
+                let value = match value {
+                    None if VALUE_OPTIONS.contains(&name) => Some(
+                        args.next()
+                            .ok_or_else(|| ConfigError::MissingValue(format!("--{name}")))?,
+                    ),
+                    value => value,
+                };
+                explicit_patterns |= matches!(name, "regexp" | "file");
+                self.set(name, value.as_deref(), pattern_files)?;
+            } else {
+                // A cluster like `-inA2`: flags up to the first option that
+                // takes a value, which gets the rest (or the next argument).
+                let cluster = &arg[1..];
+                for (i, flag) in cluster.char_indices() {
+                    let name = SHORT_OPTIONS
+                        .iter()
+                        .find(|(short, _)| *short == flag)
+                        .map(|(_, name)| *name)
+                        .ok_or_else(|| ConfigError::UnknownOption(format!("-{flag}")))?;
+                    if !VALUE_OPTIONS.contains(&name) {
+                        self.set(name, None, pattern_files)?;
+                        continue;
+                    }
+                    let rest = &cluster[i + flag.len_utf8()..];
+                    let value = if rest.is_empty() {
+                        args.next()
+                            .ok_or_else(|| ConfigError::MissingValue(format!("-{flag}")))?
+                    } else {
+                        rest.to_string()
+                    };
+                    explicit_patterns |= matches!(name, "regexp" | "file");
+                    self.set(name, Some(&value), pattern_files)?;
+                    break;
+                }
+            }
+        }
+
+        Ok(explicit_patterns)
+    }
+
+    fn set(
+        &mut self,
+        name: &str,
+        value: Option<&str>,
+        pattern_files: &mut HashMap<String, String>,
+    ) -> Result<(), ConfigError> {
+        let option = || format!("--{name}");
+
+        // --no-NAME for an option with a value forgets what it was given.
+        if let Some(forgotten) = name.strip_prefix("no-") {
+            if matches!(
+                forgotten,
+                "type" | "type-not" | "glob" | "fuzzy" | "replace"
+            ) {
+                if value.is_some() {
+                    return Err(ConfigError::UnexpectedValue(option()));
+                }
+                match forgotten {
+                    "type" => self.types.clear(),
+                    "type-not" => self.types_not.clear(),
+                    "glob" => self.globs.clear(),
+                    "fuzzy" => self.fuzzy = None,
+                    _ => self.replace = None,
+                }
+                return Ok(());
+            }
+        }
+
+        if !VALUE_OPTIONS.contains(&name) {
+            // --no-NAME turns a flag back off.
+            let (flag, on) = match name.strip_prefix("no-") {
+                Some(flag) if flag != "config" => (flag, false),
+                _ => (name, true),
+            };
+            let flag = match flag {
+                "regex" => &mut self.regex,
+                "word-regexp" => &mut self.word_regexp,
+                "line-regexp" => &mut self.line_regexp,
+                "multiline" => &mut self.multiline,
+                "ignore-case" => &mut self.ignore_case,
+                "smart-case" => &mut self.smart_case,
+                "invert-match" => &mut self.invert_match,
+                "line-number" => &mut self.line_number,
+                "count" => &mut self.count,
+                "count-matches" => &mut self.count_matches,
+                "files-with-matches" => &mut self.files_with_matches,
+                "json" => &mut self.json,
+                "stats" => &mut self.stats,
+                "decompress" => &mut self.decompress,
+                "follow" => &mut self.follow,
+                "in-place" => &mut self.in_place,
+                "dry-run" => &mut self.dry_run,
+                "no-config" if on => &mut self.no_config,
+                "help" if on => return Err(ConfigError::HelpRequested),
+                "version" if on => return Err(ConfigError::VersionRequested),
+                _ => return Err(ConfigError::UnknownOption(option())),
+            };
+            if value.is_some() {
+                return Err(ConfigError::UnexpectedValue(option()));
+            }
+            *flag = on;
+            return Ok(());
+        }
+
+        let value = value.unwrap_or_default();
+        let number = || -> Result<usize, ConfigError> {
+            value.parse().map_err(|_| ConfigError::InvalidValue {
+                option: option(),
+                value: value.to_string(),
+            })
+        };
+        match name {
+            "regexp" => self.patterns.push(value.to_string()),
+            "file" => {
+                if !pattern_files.contains_key(value) {
+                    let contents =
+                        fs::read_to_string(value).map_err(|err| ConfigError::PatternFile {
+                            path: value.to_string(),
+                            reason: err.to_string(),
+                        })?;
+                    pattern_files.insert(value.to_string(), contents);
+                }
+                self.patterns
+                    .extend(pattern_files[value].lines().map(|line| line.to_string()));
+            }
+            "after-context" => self.after_context = number()?,
+            "before-context" => self.before_context = number()?,
+            "context" => {
+                self.before_context = number()?;
+                self.after_context = self.before_context;
+            }
+            "threads" => self.threads = number()?,
+            "type" => self.types.push(value.to_string()),
+            "type-not" => self.types_not.push(value.to_string()),
+            "glob" => self.globs.push(value.to_string()),
+            "replace" => self.replace = Some(value.to_string()),
+            "fuzzy" => self.fuzzy = Some(number()?),
+            "color" => {
+                self.color = match value {
+                    "auto" => ColorChoice::Auto,
+                    "always" => ColorChoice::Always,
+                    "never" => ColorChoice::Never,
+                    _ => {
+                        return Err(ConfigError::InvalidValue {
+                            option: option(),
+                            value: value.to_string(),
+                        })
+                    }
+                }
+            }
+            _ => unreachable!("every VALUE_OPTIONS entry is handled"),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Leaves out whatever config file the machine running the tests has.
+    fn build(args: &[&str]) -> Result<Config, ConfigError> {
+        let args = ["minigrep"].iter().chain(args).map(|arg| arg.to_string());
+        Config::build_with(args, || Ok(None))
+    }
+
+    #[test]
+    fn combined_short_flags() {
+        let config = build(&["-inv", "-c", "duct", "poem.txt", "-l"]).unwrap();
+        assert!(config.ignore_case);
+        assert!(config.invert_match);
+        assert!(config.line_number);
+        assert!(config.count);
+        assert!(config.files_with_matches);
+        assert!(!config.regex);
+        assert_eq!(vec!["duct"], config.patterns);
+        assert_eq!(vec!["poem.txt"], config.paths);
+    }
+
+    #[test]
+    fn operands_after_terminator() {
+        let config = build(&["--line-number", "--", "-v", "a.txt", "--count"]).unwrap();
+        assert!(config.line_number);
+        assert!(!config.invert_match);
+        assert!(!config.count);
+        assert_eq!(vec!["-v"], config.patterns);
+        assert_eq!(vec!["a.txt", "--count"], config.paths);
+    }
+
+    #[test]
+    fn option_values() {
+        let config = build(&["-nC2", "duct", "-A", "1", "poem.txt"]).unwrap();
+        assert!(config.line_number);
+        assert_eq!((2, 1), (config.before_context, config.after_context));
+
+        let config = build(&[
+            "--before-context=3",
+            "--after-context",
+            "4",
+            "duct",
+            "poem.txt",
+        ])
+        .unwrap();
+        assert_eq!((3, 4), (config.before_context, config.after_context));
+        assert_eq!(0, config.threads);
+
+        assert_eq!(ColorChoice::Auto, config.color);
+
+        let config = build(&["-zj8", "--color=never", "duct", "a.txt", "--json"]).unwrap();
+        assert_eq!(8, config.threads);
+        assert!(config.decompress);
+        assert_eq!(ColorChoice::Never, config.color);
+        assert!(config.json);
+        assert_eq!(None, config.fuzzy);
+
+        let config = build(&["--fuzzy", "2", "recieve"]).unwrap();
+        assert_eq!(Some(2), config.fuzzy);
+
+        let config = build(&["--replace", "$1", "-E", "(duct)", "a.txt", "--in-place"]).unwrap();
+        assert_eq!(Some("$1".to_string()), config.replace);
+        assert!(config.in_place);
+        assert!(!config.dry_run);
+
+        let config = build(&["--count-matches", "--stats", "duct"]).unwrap();
+        assert!(config.count_matches && config.stats);
+        assert!(!config.count);
+
+        let config = build(&["-U", "safe,\\nfast", "-e", "a\\n"]).unwrap();
+        assert!(config.multiline);
+        assert_eq!(vec!["a\n"], config.patterns);
+        let config = build(&["-UE", "safe,\\nfast"]).unwrap();
+        assert_eq!(vec!["safe,\\nfast"], config.patterns);
+    }
+
+    #[test]
+    fn defaults_to_stdin() {
+        assert_eq!(vec!["-"], build(&["duct"]).unwrap().paths);
+    }
+
+    #[test]
+    fn multiple_patterns() {
+        let config = build(&["-e", "duct", "poem.txt", "--regexp=tape", "-wx"]).unwrap();
+        assert_eq!(vec!["duct", "tape"], config.patterns);
+        assert_eq!(vec!["poem.txt"], config.paths);
+        assert!(config.word_regexp);
+        assert!(config.line_regexp);
+
+        let path = env::temp_dir().join(format!("minigrep-patterns-{}", std::process::id()));
+        fs::write(&path, "safe\r\nfast\n\nTrust\n").unwrap();
+        let config = build(&["-educt", "-f", path.to_str().unwrap(), "-S"]).unwrap();
+        fs::remove_file(&path).unwrap();
+        // The empty line is a pattern too, and matches everything.
+        assert_eq!(vec!["duct", "safe", "fast", "", "Trust"], config.patterns);
+        assert_eq!(vec!["-"], config.paths);
+        assert!(!config.ignore_case);
+
+        assert!(matches!(
+            build(&["-f", "/nonexistent/patterns"]),
+            Err(ConfigError::PatternFile { .. })
+        ));
+    }
+
+    #[test]
+    fn file_filters() {
+        let config = build(&[
+            "-g",
+            "*.rs",
+            "--glob=!target/**",
+            "-trust",
+            "-Tjs",
+            "fn",
+            ".",
+        ])
+        .unwrap();
+        assert_eq!(vec!["*.rs", "!target/**"], config.globs);
+        assert_eq!(vec!["rust"], config.types);
+        assert_eq!(vec!["js"], config.types_not);
+
+        assert_eq!(
+            Some(ConfigError::UnknownType("cobol".to_string())),
+            build(&["--type-not=cobol", "fn"]).err()
+        );
+        assert!(matches!(
+            build(&["-g", "[z-a]", "fn"]),
+            Err(ConfigError::Glob(_))
+        ));
+    }
+
+    #[test]
+    fn smart_case() {
+        assert!(build(&["-S", "duct", "poem.txt"]).unwrap().ignore_case);
+        assert!(!build(&["-S", "Duct", "poem.txt"]).unwrap().ignore_case);
+        assert!(build(&["-S", "-i", "Duct", "poem.txt"]).unwrap().ignore_case);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(Some(ConfigError::MissingQuery), build(&["-n"]).err());
+        assert_eq!(
+            Some(ConfigError::UnknownOption("-Q".to_string())),
+            build(&["-nQ", "duct", "poem.txt"]).err()
+        );
+        assert_eq!(
+            Some(ConfigError::UnknownOption("--colour".to_string())),
+            build(&["--colour", "duct", "poem.txt"]).err()
+        );
+        assert_eq!(
+            Some(ConfigError::MissingValue("-A".to_string())),
+            build(&["duct", "poem.txt", "-A"]).err()
+        );
+        assert_eq!(
+            Some(ConfigError::InvalidValue {
+                option: "--context".to_string(),
+                value: "two".to_string()
+            }),
+            build(&["-Ctwo", "duct", "poem.txt"]).err()
+        );
+        assert_eq!(
+            Some(ConfigError::UnexpectedValue("--count".to_string())),
+            build(&["--count=3", "duct", "poem.txt"]).err()
+        );
+        assert_eq!(
+            Some(ConfigError::HelpRequested),
+            build(&["duct", "--help"]).err()
+        );
+        assert_eq!(Some(ConfigError::VersionRequested), build(&["-V"]).err());
+        assert_eq!(
+            Some(ConfigError::Requires {
+                option: "--in-place".to_string(),
+                needs: "'--replace'".to_string()
+            }),
+            build(&["--in-place", "duct", "poem.txt"]).err()
+        );
+        assert_eq!(
+            Some(ConfigError::Requires {
+                option: "--in-place".to_string(),
+                needs: "FILE operands".to_string()
+            }),
+            build(&["--in-place", "--replace=x", "duct"]).err()
+        );
+        assert_eq!(
+            Some(ConfigError::Conflict(
+                "--fuzzy".to_string(),
+                "--word-regexp".to_string()
+            )),
+            build(&["--fuzzy=1", "-w", "duct"]).err()
+        );
+        assert_eq!(
+            Some(ConfigError::Conflict(
+                "--in-place".to_string(),
+                "--multiline".to_string()
+            )),
+            build(&["-U", "--replace=x", "--in-place", "duct", "poem.txt"]).err()
+        );
+        assert_eq!(
+            Some(ConfigError::Conflict(
+                "--in-place".to_string(),
+                "--invert-match".to_string()
+            )),
+            build(&["-v", "--replace=x", "--in-place", "duct", "poem.txt"]).err()
+        );
+        assert_eq!(
+            Some(ConfigError::Requires {
+                option: "--follow".to_string(),
+                needs: "FILE operands".to_string()
+            }),
+            build(&["--follow", "ERROR"]).err()
+        );
+        assert_eq!(
+            Some(ConfigError::Conflict(
+                "--follow".to_string(),
+                "--files-with-matches".to_string()
+            )),
+            build(&["--follow", "-l", "ERROR", "app.log"]).err()
+        );
+        assert_eq!(
+            Some(ConfigError::Conflict(
+                "--follow".to_string(),
+                "--multiline".to_string()
+            )),
+            build(&["--follow", "-U", "ERROR", "app.log"]).err()
+        );
+        assert!(matches!(
+            build(&["--dry-run", "--replace", "x", "duct", "poem.txt"]),
+            Err(ConfigError::Requires { .. })
+        ));
+    }
+
+    #[test]
+    fn config_file() {
+        let rc = || {
+            RcFile::parse(
+                "minigreprc",
+                "--smart-case\n-C2\n--color=never\n[types]\nrust = *.rs\n[ignore]\ntarget/\n",
+            )
+            .map(Some)
+        };
+        let args = |args: &[&str]| {
+            ["minigrep"]
+                .iter()
+                .chain(args)
+                .map(|arg| arg.to_string())
+                .collect::<Vec<_>>()
+                .into_iter()
+        };
+
+        let config = Config::build_with(args(&["duct", "-A1", "-t", "rust", "src"]), rc).unwrap();
+        assert!(config.smart_case && config.ignore_case);
+        // The command line wins over the file.
+        assert_eq!((2, 1), (config.before_context, config.after_context));
+        assert_eq!(ColorChoice::Never, config.color);
+        assert_eq!(vec!["rust"], config.types);
+        assert_eq!(vec!["target/"], config.ignore);
+
+        let config = Config::build_with(args(&["--no-config", "duct"]), || {
+            panic!("the config file shouldn't be read")
+        })
+        .unwrap();
+        assert!(!config.smart_case);
+        assert_eq!(0, config.before_context);
+
+        // Flags the file sets can be turned off again.
+        let config = Config::build_with(
+            args(&["--no-smart-case", "-i", "--no-ignore-case", "Duct"]),
+            rc,
+        )
+        .unwrap();
+        assert!(!config.smart_case && !config.ignore_case);
+        let rc_flags = || RcFile::parse("minigreprc", "-n\n--json\n-i\n").map(Some);
+        let config =
+            Config::build_with(args(&["--no-line-number", "--no-json", "duct"]), rc_flags).unwrap();
+        assert!(!config.line_number && !config.json && config.ignore_case);
+
+        // So can the values of options that take one.
+        let rc_values = || {
+            RcFile::parse(
+                "minigreprc",
+                "--type=rust\n-Tpy\n-g*.rs\n--fuzzy=1\n--replace=y\n",
+            )
+            .map(Some)
+        };
+        let config = Config::build_with(args(&["duct"]), rc_values).unwrap();
+        assert_eq!(vec!["rust"], config.types);
+        assert_eq!(Some(1), config.fuzzy);
+        let config = Config::build_with(
+            args(&[
+                "--no-type",
+                "-tc",
+                "--no-type-not",
+                "--no-glob",
+                "--no-fuzzy",
+                "--no-replace",
+                "duct",
+            ]),
+            rc_values,
+        )
+        .unwrap();
+        assert_eq!(vec!["c"], config.types);
+        assert!(config.types_not.is_empty() && config.globs.is_empty());
+        assert_eq!((None, None), (config.fuzzy, config.replace));
+        assert_eq!(
+            Some(ConfigError::UnexpectedValue("--no-glob".to_string())),
+            Config::build_with(args(&["--no-glob=*.rs", "duct"]), rc).err()
+        );
+
+        // What's required can come from the file.
+        let rc_replace = || RcFile::parse("minigreprc", "--replace=y\n").map(Some);
+        let config =
+            Config::build_with(args(&["--dry-run", "--in-place", "x", "t.txt"]), rc_replace)
+                .unwrap();
+        assert!(config.in_place && config.dry_run);
+        assert_eq!(Some("y".to_string()), config.replace);
+        // And what conflicts is only found once both are put together.
+        let rc_follow = || RcFile::parse("minigreprc", "--follow\n").map(Some);
+        assert_eq!(
+            Some(ConfigError::Conflict(
+                "--follow".to_string(),
+                "--count".to_string()
+            )),
+            Config::build_with(args(&["-c", "x", "t.txt"]), rc_follow).err()
+        );
+        for option in ["--no-help", "--no-no-config", "--no-regexp", "--no-colour"] {
+            assert_eq!(
+                Some(ConfigError::UnknownOption(option.to_string())),
+                Config::build_with(args(&[option, "duct"]), rc).err()
+            );
+        }
+
+        // A pattern file is read once, not again for the second pass.
+        let path = env::temp_dir().join(format!("minigrep-once-{}", std::process::id()));
+        fs::write(&path, "duct\n").unwrap();
+        let config = Config::build_with(args(&["-f", path.to_str().unwrap()]), || {
+            fs::remove_file(&path).unwrap();
+            rc()
+        })
+        .unwrap();
+        assert_eq!(vec!["duct"], config.patterns);
+
+        // Types come from the file or the built-in table.
+        assert!(Config::build_with(args(&["-tproto", "duct"]), rc).is_err());
+        assert!(Config::build_with(args(&["-tpy", "duct"]), rc).is_ok());
+        for (flags, reason) in [
+            (
+                "--regexp=duct\n",
+                "only options can be set there, not patterns",
+            ),
+            ("--help\n", "'--help' and '--version' can't be set there"),
+            ("--colour=never\n", "Unknown option '--colour'"),
+        ] {
+            let rc = || RcFile::parse("minigreprc", flags).map(Some);
+            assert_eq!(
+                Some(ConfigError::RcFile {
+                    path: "minigreprc".to_string(),
+                    reason: reason.to_string()
+                }),
+                Config::build_with(args(&["duct"]), rc).err()
+            );
+        }
+    }
+}
@@ -0,0 +1,323 @@
+// `--in-place`: rewrites files with every match replaced.
+//
+// The new contents are written to a temporary file in the same directory,
+// which is then renamed over the original, so an interrupted run never
+// leaves a half-written file behind. With `--dry-run` nothing is written and
+// the changes are printed as a unified diff instead.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::Searcher;
+
+// Unchanged lines shown around each change, like `diff -u`.
+const DIFF_CONTEXT: usize = 3;
+// Temporary files made by this process so far, to tell them apart when
+// several files, or the same one named twice, are edited at once.
+static TEMP_FILES: AtomicUsize = AtomicUsize::new(0);
+
+// One line of the original file (with its line ending) and, if it changed,
+// what replaces it.
+struct Line<'a> {
+    old: &'a str,
+    new: Option<String>,
+}
+
+/// Applies the searcher's replacement to `contents`, read from the file at
+/// `path`, and writes the result back; or with `dry_run` writes the diff to
+/// `out`, labelling it with `name`. Returns how many lines were changed.
+pub fn edit_file(
+    searcher: &Searcher,
+    path: &Path,
+    name: &str,
+    contents: &str,
+    dry_run: bool,
+    out: &mut impl Write,
+) -> io::Result<usize> {
+    let lines: Vec<Line> = contents
+        .split_inclusive('\n')
+        .map(|old| {
+            let (text, ending) = split_line_ending(old);
+            // A replacement that gives back the same text isn't a change.
+            let new = searcher
+                .replace_line(text)
+                .map(|replaced| replaced + ending)
+                .filter(|new| new != old);
+            Line { old, new }
+        })
+        .collect();
+    let changed = lines.iter().filter(|line| line.new.is_some()).count();
+    if changed == 0 {
+        return Ok(0);
+    }
+
+    if dry_run {
+        write_diff(out, name, &lines)?;
+        return Ok(changed);
+    }
+    let edited: String = lines
+        .iter()
+        .map(|line| line.new.as_deref().unwrap_or(line.old))
+        .collect();
+    replace_file(path, edited.as_bytes())?;
+    Ok(changed)
+}
+
+fn split_line_ending(line: &str) -> (&str, &str) {
+    let text = line.strip_suffix('\n').unwrap_or(line);
+    let text = text.strip_suffix('\r').unwrap_or(text);
+    line.split_at(text.len())
+}
+
+fn replace_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    // Edit the file a symlink points to rather than replacing the link.
+    let path = fs::canonicalize(path)?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let (temp, mut file) = loop {
+        let temp = dir.join(format!(
+            ".{file_name}.minigrep-{}-{}",
+            process::id(),
+            TEMP_FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        // Another process that had our PID may have left one behind.
+        match OpenOptions::new().write(true).create_new(true).open(&temp) {
+            Ok(file) => break (temp, file),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(err) => return Err(err),
+        }
+    };
+
+    let mut write = || -> io::Result<()> {
+        file.write_all(contents)?;
+        file.set_permissions(fs::metadata(&path)?.permissions())?;
+        file.sync_all()?;
+        fs::rename(&temp, &path)
+    };
+    write().inspect_err(|_| {
+        let _ = fs::remove_file(&temp);
+    })
+}
+
+fn write_diff(out: &mut impl Write, name: &str, lines: &[Line]) -> io::Result<()> {
+    writeln!(out, "--- a/{name}")?;
+    writeln!(out, "+++ b/{name}")?;
+
+    let changed: Vec<usize> = (0..lines.len())
+        .filter(|&i| lines[i].new.is_some())
+        .collect();
+    // Number of lines in the new file before each line of the old one.
+    let mut new_offset = Vec::with_capacity(lines.len() + 1);
+    let mut total = 0;
+    for line in lines {
+        new_offset.push(total);
+        total += line
+            .new
+            .as_deref()
+            .map_or(1, |new| new.split_inclusive('\n').count());
+    }
+    new_offset.push(total);
+
+    let mut i = 0;
+    while i < changed.len() {
+        // Changes whose context windows touch share a hunk.
+        let mut last = i;
+        while last + 1 < changed.len() && changed[last + 1] - changed[last] <= 2 * DIFF_CONTEXT {
+            last += 1;
+        }
+        let start = changed[i].saturating_sub(DIFF_CONTEXT);
+        let end = (changed[last] + DIFF_CONTEXT + 1).min(lines.len());
+
+        writeln!(
+            out,
+            "@@ -{} +{} @@",
+            hunk_range(start, end - start),
+            hunk_range(new_offset[start], new_offset[end] - new_offset[start])
+        )?;
+        let mut j = start;
+        while j < end {
+            let Some(_) = lines[j].new else {
+                write_diff_line(out, ' ', lines[j].old)?;
+                j += 1;
+                continue;
+            };
+            // A run of changed lines: all removals, then all additions.
+            let run_end = (j..end).find(|&k| lines[k].new.is_none()).unwrap_or(end);
+            for line in &lines[j..run_end] {
+                write_diff_line(out, '-', line.old)?;
+            }
+            for line in &lines[j..run_end] {
+                for new in line
+                    .new
+                    .as_deref()
+                    .unwrap_or_default()
+                    .split_inclusive('\n')
+                {
+                    write_diff_line(out, '+', new)?;
+                }
+            }
+            j = run_end;
+        }
+        i = last + 1;
+    }
+    Ok(())
+}
+
+// `start,count` with a 0-based `start`, in `diff -u` style.
+fn hunk_range(start: usize, count: usize) -> String {
+    match count {
+        // An empty range names the line before it.
+        0 => format!("{start},0"),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{count}", start + 1),
+    }
+}
+
+fn write_diff_line(out: &mut impl Write, prefix: char, line: &str) -> io::Result<()> {
+    write!(out, "{prefix}{line}")?;
+    if !line.ends_with('\n') {
+        writeln!(out, "\n\\ No newline at end of file")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    fn searcher(pattern: &str, replace: &str) -> Searcher {
+        Searcher::new(&Config {
+            patterns: vec![pattern.to_string()],
+            regex: true,
+            replace: Some(replace.to_string()),
+            ..Config::default()
+        })
+        .unwrap()
+    }
+
+    fn temp_file(name: &str, contents: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("minigrep-edit-{}-{name}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("poem.txt");
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn rewrites_in_place() {
+        let path = temp_file("rewrite", "Rust:\r\nsafe, fast, productive.\nTrust me.");
+        let mut out = Vec::new();
+        let contents = fs::read_to_string(&path).unwrap();
+        let searcher = searcher("(T?)rust", "${1}ust");
+        assert_eq!(
+            1,
+            edit_file(&searcher, &path, "poem.txt", &contents, false, &mut out).unwrap()
+        );
+
+        assert!(out.is_empty());
+        assert_eq!(
+            "Rust:\r\nsafe, fast, productive.\nTust me.",
+            fs::read_to_string(&path).unwrap()
+        );
+        // Only the file itself is left in the directory.
+        let dir = path.parent().unwrap();
+        assert_eq!(1, fs::read_dir(dir).unwrap().count());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn same_text_is_not_a_change() {
+        let path = temp_file("same", "Rust:\nTrust me.\n");
+        let mut out = Vec::new();
+        let contents = fs::read_to_string(&path).unwrap();
+        let searcher = searcher("(T?)rust", "${1}rust");
+        assert_eq!(
+            0,
+            edit_file(&searcher, &path, "poem.txt", &contents, true, &mut out).unwrap()
+        );
+        assert!(out.is_empty());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn same_file_edited_at_once() {
+        let path = temp_file("twice", "Trust me.\n");
+        let contents = fs::read_to_string(&path).unwrap();
+        let searcher = searcher("rust", "ust");
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    edit_file(
+                        &searcher,
+                        &path,
+                        "poem.txt",
+                        &contents,
+                        false,
+                        &mut Vec::new(),
+                    )
+                    .unwrap()
+                });
+            }
+        });
+
+        assert_eq!("Tust me.\n", fs::read_to_string(&path).unwrap());
+        let dir = path.parent().unwrap();
+        assert_eq!(1, fs::read_dir(dir).unwrap().count());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn dry_run_diff() {
+        let contents: String = (1..=12).map(|i| format!("line {i}\n")).collect();
+        let path = temp_file("diff", &(contents + "line 13"));
+        let mut out = Vec::new();
+        let contents = fs::read_to_string(&path).unwrap();
+        let searcher = searcher("^line (2|9|13)$", "row $1");
+        assert_eq!(
+            3,
+            edit_file(&searcher, &path, "poem.txt", &contents, true, &mut out).unwrap()
+        );
+
+        let diff = String::from_utf8(out).unwrap();
+        assert_eq!(
+            "\
+--- a/poem.txt
++++ b/poem.txt
+@@ -1,5 +1,5 @@
+ line 1
+-line 2
++row 2
+ line 3
+ line 4
+ line 5
+@@ -6,8 +6,8 @@
+ line 6
+ line 7
+ line 8
+-line 9
++row 9
+ line 10
+ line 11
+ line 12
+-line 13
+\\ No newline at end of file
++row 13
+\\ No newline at end of file
+",
+            diff
+        );
+        assert!(fs::read_to_string(&path).unwrap().ends_with("line 13"));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn hunk_ranges() {
+        assert_eq!("3", hunk_range(2, 1));
+        assert_eq!("3,4", hunk_range(2, 4));
+        assert_eq!("2,0", hunk_range(2, 0));
+    }
+}
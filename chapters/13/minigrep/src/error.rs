@@ -0,0 +1,113 @@
+// Everything that can go wrong in a run, and the exit status it leads to.
+//
+// Like grep, minigrep exits with 0 if a line was selected, 1 if none was
+// and 2 if something went wrong. An input that can't be read doesn't end
+// the run: it's reported and the other inputs are still searched, but the
+// exit status is 2 even if they had matches.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use crate::regex::RegexError;
+use crate::ConfigError;
+
+#[derive(Debug)]
+pub enum MinigrepError {
+    /// Bad command line arguments.
+    Usage(ConfigError),
+    /// Reading an input failed (`path` names it), or writing the output
+    /// did (no `path`).
+    Io {
+        path: Option<String>,
+        source: io::Error,
+    },
+    /// A pattern or `--replace` text that doesn't parse.
+    Pattern(RegexError),
+    /// A file `--in-place` won't edit because it isn't UTF-8.
+    Encoding { path: String },
+}
+
+impl MinigrepError {
+    pub(crate) fn io(path: &str, source: io::Error) -> MinigrepError {
+        MinigrepError::Io {
+            path: Some(path.to_string()),
+            source,
+        }
+    }
+
+    /// Whether the output was closed early (`minigrep ... | head`), which
+    /// isn't worth complaining about.
+    pub fn is_broken_pipe(&self) -> bool {
+        matches!(
+            self,
+            MinigrepError::Io { path: None, source } if source.kind() == io::ErrorKind::BrokenPipe
+        )
+    }
+}
+
+impl fmt::Display for MinigrepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MinigrepError::Usage(err) => write!(f, "{err}"),
+            MinigrepError::Io {
+                path: Some(path),
+                source,
+            } => write!(f, "{path}: {source}"),
+            MinigrepError::Io { path: None, source } => write!(f, "{source}"),
+            MinigrepError::Pattern(err) => write!(f, "{err}"),
+            MinigrepError::Encoding { path } => {
+                write!(f, "{path}: not valid UTF-8, refusing to edit it")
+            }
+        }
+    }
+}
+
+impl Error for MinigrepError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MinigrepError::Usage(err) => Some(err),
+            MinigrepError::Io { source, .. } => Some(source),
+            MinigrepError::Pattern(err) => Some(err),
+            MinigrepError::Encoding { .. } => None,
+        }
+    }
+}
+
+impl From<ConfigError> for MinigrepError {
+    fn from(err: ConfigError) -> MinigrepError {
+        MinigrepError::Usage(err)
+    }
+}
+
+impl From<RegexError> for MinigrepError {
+    fn from(err: RegexError) -> MinigrepError {
+        MinigrepError::Pattern(err)
+    }
+}
+
+// Errors writing the output.
+impl From<io::Error> for MinigrepError {
+    fn from(source: io::Error) -> MinigrepError {
+        MinigrepError::Io { path: None, source }
+    }
+}
+
+/// How a run went, as far as the exit status is concerned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Matched,
+    NoMatch,
+    /// Some input couldn't be searched (and has been reported).
+    Error,
+}
+
+impl Status {
+    pub fn code(self) -> i32 {
+        match self {
+            Status::Matched => 0,
+            Status::NoMatch => 1,
+            Status::Error => 2,
+        }
+    }
+}
@@ -0,0 +1,197 @@
+// Unicode case folding for case-insensitive matching.
+//
+// `str::to_lowercase` isn't quite case folding: "STRASSE" lowercases to
+// "strasse" but "Straße" to "straße", so the two never compare equal, and it
+// allocates a new String for every line searched. Here each char is folded
+// on the fly into at most three chars on the stack, using full case folding
+// (ß → ss, ﬁ → fi, ς → σ, ...) on top of `char::to_lowercase`.
+//
+// Turkish dotted and dotless i are both folded to a plain `i`, so a query
+// finds Turkish text whichever convention either side was written in.
+
+/// The case-folded form of a single char: one to three chars.
+#[derive(Debug, Clone)]
+pub struct Folded {
+    chars: [char; 3],
+    len: u8,
+    pos: u8,
+}
+
+impl Iterator for Folded {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        if self.pos == self.len {
+            return None;
+        }
+        self.pos += 1;
+        Some(self.chars[usize::from(self.pos - 1)])
+    }
+}
+
+// Folds that `char::to_lowercase` doesn't already give us.
+fn special(c: char) -> Option<&'static str> {
+    Some(match c {
+        'ß' | 'ẞ' => "ss",
+        'İ' | 'ı' => "i",
+        'ſ' => "s",
+        'ς' => "σ",
+        'ϐ' => "β",
+        'ϑ' => "θ",
+        'ϕ' => "φ",
+        'ϖ' => "π",
+        'ϰ' => "κ",
+        'ϱ' => "ρ",
+        'ϵ' => "ε",
+        'ẛ' => "ṡ",
+        'ŉ' => "ʼn",
+        'ﬀ' => "ff",
+        'ﬁ' => "fi",
+        'ﬂ' => "fl",
+        'ﬃ' => "ffi",
+        'ﬄ' => "ffl",
+        'ﬅ' | 'ﬆ' => "st",
+        _ => return None,
+    })
+}
+
+pub fn fold(c: char) -> Folded {
+    let mut folded = Folded {
+        chars: ['\0'; 3],
+        len: 0,
+        pos: 0,
+    };
+    let mut push = |c| {
+        folded.chars[usize::from(folded.len)] = c;
+        folded.len += 1;
+    };
+    if c.is_ascii() {
+        push(c.to_ascii_lowercase());
+    } else if let Some(s) = special(c) {
+        s.chars().for_each(push);
+    } else {
+        // Lowercase mappings are at most three chars long.
+        c.to_lowercase().for_each(push);
+    }
+    folded
+}
+
+/// Single-char approximation of `fold`, for places that compare one char
+/// against another (like regex classes).
+pub fn fold_char(c: char) -> char {
+    let mut folded = fold(c);
+    match (folded.next(), folded.next()) {
+        (Some(single), None) => single,
+        // Expansions like ß → ss don't fit in one char.
+        _ => c,
+    }
+}
+
+/// The folded form of a whole query, computed once before searching.
+pub fn fold_str(s: &str) -> Vec<char> {
+    s.chars().flat_map(fold).collect()
+}
+
+/// Byte range of the first place at or after `start` where `haystack`, once
+/// folded, reads `needle` (which must already be folded). Matches begin and
+/// end on char boundaries of `haystack`.
+pub fn find(haystack: &str, needle: &[char], start: usize) -> Option<(usize, usize)> {
+    let Some(&first) = needle.first() else {
+        return Some((start, start));
+    };
+    // ASCII only ever folds to ASCII, and apart from these four an ASCII
+    // letter only ever comes from itself or its uppercase form (ß → ss,
+    // ﬁ → fi, K → k, İ → i). So candidates can be found by scanning bytes,
+    // only decoding the chars that could fold to `first`, without first
+    // looking over the whole line to see whether it's ASCII.
+    let ascii = first
+        .is_ascii()
+        .then(|| (first as u8, first.to_ascii_uppercase() as u8));
+    let from_non_ascii = !first.is_ascii() || matches!(first, 'f' | 'i' | 'k' | 's');
+    let bytes = haystack.as_bytes();
+    (start..bytes.len()).find_map(|i| {
+        let candidate = if bytes[i].is_ascii() {
+            ascii.is_some_and(|(lower, upper)| bytes[i] == lower || bytes[i] == upper)
+        } else {
+            from_non_ascii && haystack.is_char_boundary(i)
+        };
+        if !candidate {
+            return None;
+        }
+        match_len(&haystack[i..], needle).map(|len| (i, i + len))
+    })
+}
+
+// Like `prefix_len`, but an ASCII start of `text` is compared byte by byte.
+fn match_len(text: &str, needle: &[char]) -> Option<usize> {
+    match text.as_bytes().get(..needle.len()) {
+        Some(start) if start.is_ascii() => start
+            .iter()
+            .zip(needle)
+            .all(|(&b, &c)| u32::from(b.to_ascii_lowercase()) == u32::from(c))
+            .then_some(needle.len()),
+        _ => prefix_len(text, needle),
+    }
+}
+
+// How many bytes of `text` fold to exactly `needle`, if its start does.
+fn prefix_len(text: &str, needle: &[char]) -> Option<usize> {
+    let mut want = needle.iter();
+    for (i, c) in text.char_indices() {
+        if want.len() == 0 {
+            return Some(i);
+        }
+        for folded in fold(c) {
+            if want.next() != Some(&folded) {
+                return None;
+            }
+        }
+    }
+    (want.len() == 0).then_some(text.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find_str(haystack: &str, needle: &str) -> Option<(usize, usize)> {
+        find(haystack, &fold_str(needle), 0)
+    }
+
+    #[test]
+    fn full_folding() {
+        assert_eq!(Some((0, 7)), find_str("Straße", "STRASSE"));
+        assert_eq!(Some((0, 7)), find_str("STRASSE", "straße"));
+        assert_eq!(Some((4, 7)), find_str("the ﬁle", "FI"));
+        assert_eq!(Some((0, 6)), find_str("ΣΑΣ", "σας"));
+        assert_eq!(Some((5, 9)), find_str("Σ: Trust", "rust"));
+        assert_eq!(Some((0, 5)), find_str("\u{212a}ey", "KEY"));
+        // Half of an expansion isn't a match.
+        assert_eq!(None, find_str("ß", "s"));
+    }
+
+    #[test]
+    fn turkish_i() {
+        assert_eq!(Some((0, 9)), find_str("İstanbul", "istanbul"));
+        assert_eq!(Some((0, 8)), find_str("ıspanak", "ISPANAK"));
+        assert_eq!('i', fold_char('İ'));
+    }
+
+    #[test]
+    fn ascii_lines() {
+        assert_eq!(Some((2, 7)), find_str("a Trust me.", "TRUST"));
+        assert_eq!(Some((7, 11)), find("Trust trust", &fold_str("rust"), 2));
+        assert_eq!(None, find_str("Trust", "TRUSTY"));
+        assert_eq!(None, find_str("Trust", "trüst"));
+        assert_eq!(Some((3, 3)), find("abc", &[], 3));
+        assert_eq!(None, find("abc", &fold_str("c"), 3));
+    }
+
+    #[test]
+    fn offsets_are_in_the_haystack() {
+        assert_eq!(Some((4, 8)), find_str("İ: RuST TRUST", "rust"));
+        assert_eq!(Some((10, 14)), find("İ: RuST TRUST", &fold_str("rust"), 5));
+        assert_eq!(Some((7, 9)), find("Trust: ß", &fold_str("SS"), 0));
+        assert_eq!(Some((4, 7)), find("ß: ẞẞ", &fold_str("ss"), 1));
+    }
+}
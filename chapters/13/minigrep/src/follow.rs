@@ -0,0 +1,376 @@
+// `--follow`: keeps searching files as they grow, like `tail -f | grep`.
+//
+// There's no OS notification API behind this, just polling: each `poll`
+// looks at the file's metadata and searches whatever was appended since the
+// last one. A file that got shorter was truncated and is searched again from
+// the start. A different file at the same path (a different inode) means the
+// old one was rotated away; the rest of the old file is searched before
+// moving on to the new one from its start. Lines are only searched once
+// they're complete, so a line still being written waits for its newline.
+//
+// Context can run from one poll into the next, so the last few lines
+// searched are kept and searched again in front of the new ones, with
+// whatever was already reported for them left out.
+
+use std::fs::{self, File, Metadata};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use crate::memmem;
+use crate::searcher::{FileSummary, Renumbered, Searcher, Sink, SinkContext, SinkMatch};
+
+pub struct Follower {
+    path: PathBuf,
+    name: String,
+    file: File,
+    // Which file `file` is, to notice when the path gets another one.
+    id: Option<(u64, u64)>,
+    // How much of `file` has been read.
+    offset: u64,
+    // Lines already searched, kept for the context of the next ones, then
+    // the start of a line whose end hasn't been written yet.
+    partial: Vec<u8>,
+    // How many of `partial`'s lines, and bytes, were already searched.
+    kept_lines: usize,
+    kept_len: usize,
+    // Number of the next complete line.
+    line_number: usize,
+    // The last line reported, to tell which kept lines were and where
+    // context breaks go.
+    last_reported: Option<usize>,
+    // Whether a context break is due before the next line reported, for
+    // starting the file over.
+    break_due: bool,
+}
+
+impl Follower {
+    /// Starts following the file at `path`, which has to exist now. Its
+    /// current contents are searched by the first `poll`.
+    pub fn open(path: &Path, name: &str) -> io::Result<Follower> {
+        let file = File::open(path)?;
+        let metadata = file.metadata()?;
+        if metadata.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "can't follow a directory",
+            ));
+        }
+        let id = file_id(&metadata);
+        Ok(Follower {
+            path: path.to_path_buf(),
+            name: name.to_string(),
+            file,
+            id,
+            offset: 0,
+            partial: Vec::new(),
+            kept_lines: 0,
+            kept_len: 0,
+            line_number: 1,
+            last_reported: None,
+            break_due: false,
+        })
+    }
+
+    /// Searches what has been added to the file since the last poll,
+    /// reporting it to `sink` under the name given to `open`.
+    pub fn poll(&mut self, searcher: &Searcher, sink: &mut impl Sink) -> io::Result<FileSummary> {
+        let mut summary = FileSummary::default();
+        let metadata = match fs::metadata(&self.path) {
+            Ok(metadata) => Some(metadata),
+            // Between a rotation and the new file being created.
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err),
+        };
+
+        let rotated = metadata
+            .as_ref()
+            .is_some_and(|metadata| self.id.is_some() && file_id(metadata) != self.id);
+        if rotated {
+            // Whatever was written to the old file before it moved.
+            let len = self.file.metadata()?.len();
+            self.read_to(len, searcher, sink, &mut summary)?;
+            self.search_rest(true, searcher, sink, &mut summary)?;
+
+            self.file = File::open(&self.path)?;
+            self.id = file_id(&self.file.metadata()?);
+            self.restart();
+        }
+
+        let len = self.file.metadata()?.len();
+        if len < self.offset {
+            // Truncated: the file starts over, and so do its lines.
+            self.restart();
+        }
+        self.read_to(len, searcher, sink, &mut summary)?;
+        Ok(summary)
+    }
+
+    fn restart(&mut self) {
+        self.offset = 0;
+        self.partial.clear();
+        self.kept_lines = 0;
+        self.kept_len = 0;
+        self.line_number = 1;
+        self.break_due |= self.last_reported.take().is_some();
+    }
+
+    // Reads the file up to byte `len` and searches the lines completed by
+    // it.
+    fn read_to(
+        &mut self,
+        len: u64,
+        searcher: &Searcher,
+        sink: &mut impl Sink,
+        summary: &mut FileSummary,
+    ) -> io::Result<()> {
+        if len <= self.offset {
+            return Ok(());
+        }
+        self.file.seek(SeekFrom::Start(self.offset))?;
+        let read = (&mut self.file)
+            .take(len - self.offset)
+            .read_to_end(&mut self.partial)?;
+        self.offset += read as u64;
+        self.search_rest(false, searcher, sink, summary)
+    }
+
+    // Searches the complete lines read so far, or with `all` every line,
+    // finished or not.
+    fn search_rest(
+        &mut self,
+        all: bool,
+        searcher: &Searcher,
+        sink: &mut impl Sink,
+        summary: &mut FileSummary,
+    ) -> io::Result<()> {
+        let end = if all {
+            self.partial.len()
+        } else {
+            memmem::memrchr(b'\n', &self.partial).map_or(0, |i| i + 1)
+        };
+        if end <= self.kept_len {
+            return Ok(());
+        }
+
+        let lines = &self.partial[..end];
+        let (before, after) = searcher.context();
+        let mut fresh = Fresh {
+            inner: sink,
+            last_reported: &mut self.last_reported,
+            break_due: &mut self.break_due,
+            breaks: before > 0 || after > 0,
+            skipped: 0,
+        };
+        let mut sink = Renumbered {
+            inner: &mut fresh,
+            offset: self.line_number - 1 - self.kept_lines,
+        };
+        let found = searcher.search_reader(&self.name, lines, &mut sink)?;
+        summary.matched_lines += found.matched_lines - fresh.skipped;
+        // Only counted with --count-matches and --stats, which leave out
+        // context, so nothing was searched twice.
+        summary.matches += found.matches;
+        summary.bytes_searched += (end - self.kept_len) as u64;
+        summary.elapsed += found.elapsed;
+        self.line_number += memmem::count(b'\n', &lines[self.kept_len..]);
+
+        // Keep enough lines for the before context of the next ones, and
+        // for the after context of any selected among them.
+        let keep = before.max(after);
+        let mut kept_from = end;
+        self.kept_lines = 0;
+        while self.kept_lines < keep && kept_from > 0 {
+            kept_from = memmem::memrchr(b'\n', &self.partial[..kept_from - 1]).map_or(0, |i| i + 1);
+            self.kept_lines += 1;
+        }
+        self.partial.drain(..kept_from);
+        self.kept_len = end - kept_from;
+        Ok(())
+    }
+}
+
+// Passes on what's reported for lines that weren't already, as kept lines
+// searched again may have been. Context breaks are placed here, as they can
+// fall between two polls.
+struct Fresh<'s, S> {
+    inner: &'s mut S,
+    last_reported: &'s mut Option<usize>,
+    break_due: &'s mut bool,
+    breaks: bool,
+    // Selected lines left out.
+    skipped: usize,
+}
+
+impl<S: Sink> Fresh<'_, S> {
+    // Whether the line numbered `line_number` is new, breaking off the
+    // context before it if it doesn't follow on from the last one.
+    fn report(&mut self, line_number: usize, end_line_number: usize) -> io::Result<bool> {
+        let last = *self.last_reported;
+        if last.is_some_and(|last| line_number <= last) {
+            return Ok(false);
+        }
+        let gap = last.is_some_and(|last| last + 1 != line_number);
+        if self.breaks && (gap || *self.break_due) {
+            self.inner.context_break()?;
+        }
+        *self.break_due = false;
+        *self.last_reported = Some(end_line_number);
+        Ok(true)
+    }
+}
+
+impl<S: Sink> Sink for Fresh<'_, S> {
+    fn begin(&mut self, name: &str) -> io::Result<()> {
+        self.inner.begin(name)
+    }
+
+    fn matched(&mut self, line: &SinkMatch<'_>) -> io::Result<bool> {
+        if !self.report(line.line_number, line.end_line_number)? {
+            self.skipped += line.end_line_number + 1 - line.line_number;
+            return Ok(true);
+        }
+        self.inner.matched(line)
+    }
+
+    fn context(&mut self, line: &SinkContext<'_>) -> io::Result<bool> {
+        if !self.report(line.line_number, line.line_number)? {
+            return Ok(true);
+        }
+        self.inner.context(line)
+    }
+
+    fn context_break(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn end(&mut self, name: &str, summary: &FileSummary) -> io::Result<()> {
+        self.inner.end(name, summary)
+    }
+}
+
+// What tells one file from another at the same path: its device and inode
+// where there are such things. Elsewhere only truncation is noticed.
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+    use std::env;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+
+    // The selected lines, as "number:line", context lines as "number-line",
+    // and context breaks.
+    #[derive(Default)]
+    struct Lines(Vec<String>);
+
+    impl Sink for Lines {
+        fn matched(&mut self, line: &SinkMatch<'_>) -> io::Result<bool> {
+            self.0.push(format!("{}:{}", line.line_number, line.line));
+            Ok(true)
+        }
+
+        fn context(&mut self, line: &SinkContext<'_>) -> io::Result<bool> {
+            self.0.push(format!("{}-{}", line.line_number, line.line));
+            Ok(true)
+        }
+
+        fn context_break(&mut self) -> io::Result<()> {
+            self.0.push("--".to_string());
+            Ok(())
+        }
+    }
+
+    fn append(path: &Path, text: &str) {
+        let mut file = OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn growth_truncation_and_rotation() {
+        let dir = env::temp_dir().join(format!("minigrep-follow-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+        fs::write(&path, "INFO up\nERROR one\n").unwrap();
+
+        let searcher = Searcher::new(&Config {
+            patterns: vec!["ERROR".to_string()],
+            ..Config::default()
+        })
+        .unwrap();
+        let mut follower = Follower::open(&path, "app.log").unwrap();
+        let mut poll = || {
+            let mut lines = Lines::default();
+            follower.poll(&searcher, &mut lines).unwrap();
+            lines.0
+        };
+
+        assert_eq!(vec!["2:ERROR one"], poll());
+        assert!(poll().is_empty());
+
+        // A line is only searched once it's finished.
+        append(&path, "INFO fine\nERROR tw");
+        assert!(poll().is_empty());
+        append(&path, "o\n");
+        assert_eq!(vec!["4:ERROR two"], poll());
+
+        fs::write(&path, "ERROR three\n").unwrap();
+        assert_eq!(vec!["1:ERROR three"], poll());
+
+        // The rest of the old file comes before the new one.
+        append(&path, "ERROR four");
+        fs::rename(&path, dir.join("app.log.1")).unwrap();
+        assert!(poll().is_empty());
+        fs::write(&path, "INFO again\nERROR five\n").unwrap();
+        assert_eq!(vec!["2:ERROR four", "2:ERROR five"], poll());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn context_across_polls() {
+        let dir = env::temp_dir().join(format!("minigrep-follow-context-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+        fs::write(&path, "x\nERROR a\n").unwrap();
+
+        let searcher = Searcher::new(&Config {
+            patterns: vec!["ERROR".to_string()],
+            before_context: 1,
+            after_context: 1,
+            ..Config::default()
+        })
+        .unwrap();
+        let mut follower = Follower::open(&path, "app.log").unwrap();
+        let mut poll = || {
+            let mut lines = Lines::default();
+            follower.poll(&searcher, &mut lines).unwrap();
+            lines.0
+        };
+
+        assert_eq!(vec!["1-x", "2:ERROR a"], poll());
+        append(&path, "after1\nafter2\n");
+        assert_eq!(vec!["3-after1"], poll());
+        append(&path, "before\n");
+        assert!(poll().is_empty());
+        append(&path, "ERROR b\nafter3\n");
+        assert_eq!(vec!["--", "5-before", "6:ERROR b", "7-after3"], poll());
+        append(&path, "ERROR c\n");
+        assert_eq!(vec!["8:ERROR c"], poll());
+        // Starting over breaks off the context too.
+        fs::write(&path, "ERROR d\n").unwrap();
+        assert_eq!(vec!["--", "1:ERROR d"], poll());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,339 @@
+// Approximate matching for `--fuzzy`: finds a pattern anywhere in a line
+// with at most K edits (insertions, deletions or substitutions of one char),
+// so "recieve" still finds "receive".
+//
+// Patterns of up to 64 chars use Myers' bit-parallel algorithm: one column
+// of the edit distance table is kept as bit vectors of +1/-1 differences
+// between neighbouring cells, and a whole column is advanced with a handful
+// of word operations per char of text. Longer patterns fall back to the
+// plain dynamic programming table, one column at a time.
+
+use crate::fold::fold_char;
+
+const WORD_BITS: usize = u64::BITS as usize;
+
+#[derive(Debug)]
+pub struct Fuzzy {
+    pattern: Vec<char>,
+    max_edits: usize,
+    ignore_case: bool,
+    // For each char, the pattern positions it appears at (patterns of up to
+    // 64 chars only). ASCII gets a table; anything else a short list.
+    ascii_masks: [u64; 128],
+    other_masks: Vec<(char, u64)>,
+}
+
+/// An approximate occurrence: the byte range it spans and its distance from
+/// the pattern.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FuzzyMatch {
+    pub start: usize,
+    pub end: usize,
+    pub distance: usize,
+}
+
+impl Fuzzy {
+    pub fn new(pattern: &str, max_edits: usize, ignore_case: bool) -> Fuzzy {
+        let pattern: Vec<char> = pattern
+            .chars()
+            .map(|c| if ignore_case { fold_char(c) } else { c })
+            .collect();
+        let mut ascii_masks = [0; 128];
+        let mut other_masks: Vec<(char, u64)> = Vec::new();
+        if pattern.len() <= WORD_BITS {
+            for (i, &c) in pattern.iter().enumerate() {
+                let bit = 1 << i;
+                if c.is_ascii() {
+                    ascii_masks[c as usize] |= bit;
+                } else if let Some((_, mask)) = other_masks.iter_mut().find(|(o, _)| *o == c) {
+                    *mask |= bit;
+                } else {
+                    other_masks.push((c, bit));
+                }
+            }
+        }
+        Fuzzy {
+            pattern,
+            max_edits,
+            ignore_case,
+            ascii_masks,
+            other_masks,
+        }
+    }
+
+    /// The fewest edits that make the pattern occur somewhere in `text`, if
+    /// that's no more than the limit.
+    pub fn distance(&self, text: &str) -> Option<usize> {
+        let mut scanner = Scanner::new(self);
+        let mut best = scanner.score();
+        for c in text.chars() {
+            best = best.min(scanner.step(c));
+            if best == 0 {
+                break;
+            }
+        }
+        (best <= self.max_edits).then_some(best)
+    }
+
+    /// The first occurrence within the limit that ends at or after byte
+    /// offset `start` (and begins there or later). Of the overlapping
+    /// candidates, the closest one is taken.
+    pub fn find_at(&self, text: &str, start: usize) -> Option<FuzzyMatch> {
+        let mut scanner = Scanner::new(self);
+        if scanner.score() <= self.max_edits {
+            // The pattern is short enough to delete outright.
+            return Some(FuzzyMatch {
+                start,
+                end: start,
+                distance: scanner.score(),
+            });
+        }
+
+        let mut found: Option<(usize, usize)> = None;
+        for (i, c) in text[start..].char_indices() {
+            let score = scanner.step(c);
+            let end = start + i + c.len_utf8();
+            match found {
+                // Keep going while the distance doesn't get worse, so the
+                // whole of "receive" is taken rather than just "receiv".
+                Some((_, best)) if score <= best => found = Some((end, score)),
+                Some(_) => break,
+                None if score <= self.max_edits => found = Some((end, score)),
+                None => {}
+            }
+            if score == 0 {
+                break;
+            }
+        }
+
+        let (end, distance) = found?;
+        Some(FuzzyMatch {
+            start: self.start_of(text, start, end, distance),
+            end,
+            distance,
+        })
+    }
+
+    // Where an occurrence with `distance` edits that ends at `end` begins:
+    // the nearest start that actually achieves it, found by aligning the
+    // pattern backwards from `end`.
+    fn start_of(&self, text: &str, from: usize, end: usize, distance: usize) -> usize {
+        let m = self.pattern.len();
+        // column[i]: distance between the last i pattern chars and the text
+        // read so far (backwards from `end`).
+        let mut column: Vec<usize> = (0..=m).collect();
+        if column[m] == distance {
+            return end;
+        }
+        for (j, (i, c)) in text[from..end].char_indices().rev().enumerate() {
+            let c = self.normalize(c);
+            let mut diagonal = column[0];
+            column[0] = j + 1;
+            for k in 1..=m {
+                let cost = usize::from(self.pattern[m - k] != c);
+                let cell = (diagonal + cost).min(column[k] + 1).min(column[k - 1] + 1);
+                diagonal = column[k];
+                column[k] = cell;
+            }
+            if column[m] == distance {
+                return from + i;
+            }
+        }
+        from
+    }
+
+    fn normalize(&self, c: char) -> char {
+        if self.ignore_case {
+            fold_char(c)
+        } else {
+            c
+        }
+    }
+
+    fn mask(&self, c: char) -> u64 {
+        if c.is_ascii() {
+            self.ascii_masks[c as usize]
+        } else {
+            self.other_masks
+                .iter()
+                .find(|(other, _)| *other == c)
+                .map_or(0, |&(_, mask)| mask)
+        }
+    }
+}
+
+// Feeds text to a `Fuzzy` one char at a time, tracking the distance of the
+// best occurrence ending at the current position.
+enum Scanner<'a> {
+    Bits {
+        fuzzy: &'a Fuzzy,
+        // Vertical +1 and -1 differences of the current column.
+        plus: u64,
+        minus: u64,
+        last_bit: u64,
+        score: usize,
+    },
+    Table {
+        fuzzy: &'a Fuzzy,
+        column: Vec<usize>,
+    },
+}
+
+impl<'a> Scanner<'a> {
+    fn new(fuzzy: &'a Fuzzy) -> Scanner<'a> {
+        let m = fuzzy.pattern.len();
+        if m > WORD_BITS {
+            return Scanner::Table {
+                fuzzy,
+                column: (0..=m).collect(),
+            };
+        }
+        Scanner::Bits {
+            fuzzy,
+            plus: u64::MAX,
+            minus: 0,
+            last_bit: if m == 0 { 0 } else { 1 << (m - 1) },
+            score: m,
+        }
+    }
+
+    fn score(&self) -> usize {
+        match self {
+            Scanner::Bits { score, .. } => *score,
+            Scanner::Table { column, .. } => column[column.len() - 1],
+        }
+    }
+
+    fn step(&mut self, c: char) -> usize {
+        match self {
+            Scanner::Bits {
+                fuzzy,
+                plus,
+                minus,
+                last_bit,
+                score,
+            } => {
+                if *last_bit == 0 {
+                    return 0;
+                }
+                let eq = fuzzy.mask(fuzzy.normalize(c));
+                let xv = eq | *minus;
+                let xh = ((eq & *plus).wrapping_add(*plus) ^ *plus) | eq;
+                let mut ph = *minus | !(xh | *plus);
+                let mut mh = *plus & xh;
+                if ph & *last_bit != 0 {
+                    *score += 1;
+                } else if mh & *last_bit != 0 {
+                    *score -= 1;
+                }
+                // The top row stays 0: an occurrence may start anywhere.
+                ph <<= 1;
+                mh <<= 1;
+                *plus = mh | !(xv | ph);
+                *minus = ph & xv;
+                *score
+            }
+            Scanner::Table { fuzzy, column } => {
+                let c = fuzzy.normalize(c);
+                let mut diagonal = column[0];
+                for k in 1..column.len() {
+                    let cost = usize::from(fuzzy.pattern[k - 1] != c);
+                    let cell = (diagonal + cost).min(column[k] + 1).min(column[k - 1] + 1);
+                    diagonal = column[k];
+                    column[k] = cell;
+                }
+                column[column.len() - 1]
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Straightforward reference: the best distance over every substring.
+    fn naive_distance(pattern: &str, text: &str) -> usize {
+        let pattern: Vec<char> = pattern.chars().collect();
+        let text: Vec<char> = text.chars().collect();
+        let mut best = pattern.len();
+        for start in 0..=text.len() {
+            let mut row: Vec<usize> = (0..=pattern.len()).collect();
+            for &c in &text[start..] {
+                let mut diagonal = row[0];
+                row[0] += 1;
+                for k in 1..row.len() {
+                    let cell = (diagonal + usize::from(pattern[k - 1] != c))
+                        .min(row[k] + 1)
+                        .min(row[k - 1] + 1);
+                    diagonal = row[k];
+                    row[k] = cell;
+                }
+                best = best.min(row[pattern.len()]);
+            }
+        }
+        best
+    }
+
+    #[test]
+    fn finds_typos() {
+        let fuzzy = Fuzzy::new("recieve", 2, false);
+        assert_eq!(Some(2), fuzzy.distance("failed to receive packet"));
+        assert_eq!(
+            Some(FuzzyMatch {
+                start: 10,
+                end: 17,
+                distance: 2
+            }),
+            fuzzy.find_at("failed to receive packet", 0)
+        );
+        assert_eq!(Some(0), fuzzy.distance("recieve"));
+        assert_eq!(None, Fuzzy::new("recieve", 1, false).distance("receive"));
+        assert_eq!(Some(1), Fuzzy::new("recieve", 1, false).distance("reciive"));
+        assert_eq!(Some(1), Fuzzy::new("recieve", 1, false).distance("recive"));
+    }
+
+    #[test]
+    fn matches_reference() {
+        let texts = [
+            "",
+            "a",
+            "banana",
+            "abracadabra",
+            "cabbage bag",
+            "naïve café",
+        ];
+        let patterns = ["", "a", "nab", "bra", "abcab", "gab", "cafe", "ïv"];
+        for pattern in patterns {
+            for text in texts {
+                let expected = naive_distance(pattern, text);
+                assert_eq!(
+                    Some(expected),
+                    Fuzzy::new(pattern, 99, false).distance(text),
+                    "{pattern:?} in {text:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn long_patterns() {
+        let pattern = "x".repeat(70) + "needle";
+        let text = format!("hay {}nedle hay", "x".repeat(70));
+        let fuzzy = Fuzzy::new(&pattern, 3, false);
+        assert_eq!(Some(1), fuzzy.distance(&text));
+        let hit = fuzzy.find_at(&text, 0).unwrap();
+        assert_eq!((4, 79, 1), (hit.start, hit.end, hit.distance));
+    }
+
+    #[test]
+    fn ignore_case_and_offsets() {
+        let fuzzy = Fuzzy::new("CAFE", 1, true);
+        let hit = fuzzy.find_at("Déjà vu: café au lait", 0).unwrap();
+        assert_eq!(
+            ("café", 1),
+            (&"Déjà vu: café au lait"[hit.start..hit.end], hit.distance)
+        );
+        assert_eq!(None, fuzzy.find_at("Déjà vu: café au lait", hit.end));
+    }
+}
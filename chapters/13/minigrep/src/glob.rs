@@ -0,0 +1,245 @@
+// Shell-style glob patterns, matched against `/`-separated relative paths.
+//
+//   `*`      any run of characters except `/`
+//   `?`      one character except `/`
+//   `[...]`  a character class, `[!...]` or `[^...]` to negate
+//   `**`     as a whole path component, any number of directories
+//   `\c`     the literal character `c`
+
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GlobError {
+    pattern: String,
+    msg: &'static str,
+}
+
+impl fmt::Display for GlobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid glob '{}': {}", self.pattern, self.msg)
+    }
+}
+
+impl Error for GlobError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Char(char),
+    AnyChar,
+    Star,
+    // `**/`: nothing, or any path ending in `/`.
+    AnyDirs,
+    // A trailing `/**` component (or a lone `**`): anything at all.
+    AnyPath,
+    Class {
+        ranges: Vec<(char, char)>,
+        negated: bool,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Glob {
+    pattern: String,
+    tokens: Vec<Token>,
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Result<Glob, GlobError> {
+        let error = |msg| GlobError {
+            pattern: pattern.to_string(),
+            msg,
+        };
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '*' if chars.get(i + 1) == Some(&'*') => {
+                    let starts_component = i == 0 || chars[i - 1] == '/';
+                    match chars.get(i + 2) {
+                        Some('/') if starts_component => {
+                            tokens.push(Token::AnyDirs);
+                            i += 3;
+                        }
+                        None if starts_component => {
+                            tokens.push(Token::AnyPath);
+                            i += 2;
+                        }
+                        // `a**b` is just two stars.
+                        _ => {
+                            tokens.push(Token::Star);
+                            i += 2;
+                        }
+                    }
+                }
+                '*' => {
+                    tokens.push(Token::Star);
+                    i += 1;
+                }
+                '?' => {
+                    tokens.push(Token::AnyChar);
+                    i += 1;
+                }
+                '\\' => {
+                    let c = *chars
+                        .get(i + 1)
+                        .ok_or_else(|| error("trailing backslash"))?;
+                    tokens.push(Token::Char(c));
+                    i += 2;
+                }
+                '[' => {
+                    i += 1;
+                    let negated = matches!(chars.get(i), Some('!' | '^'));
+                    if negated {
+                        i += 1;
+                    }
+                    let mut ranges = Vec::new();
+                    let mut first = true;
+                    loop {
+                        let c = *chars
+                            .get(i)
+                            .ok_or_else(|| error("unclosed character class"))?;
+                        i += 1;
+                        if c == ']' && !first {
+                            break;
+                        }
+                        first = false;
+                        let lo = if c == '\\' {
+                            i += 1;
+                            *chars
+                                .get(i - 1)
+                                .ok_or_else(|| error("trailing backslash"))?
+                        } else {
+                            c
+                        };
+                        if chars.get(i) == Some(&'-') && chars.get(i + 1).is_some_and(|&c| c != ']')
+                        {
+                            let hi = chars[i + 1];
+                            if hi < lo {
+                                return Err(error("invalid character range"));
+                            }
+                            ranges.push((lo, hi));
+                            i += 2;
+                        } else {
+                            ranges.push((lo, lo));
+                        }
+                    }
+                    tokens.push(Token::Class { ranges, negated });
+                }
+                c => {
+                    tokens.push(Token::Char(c));
+                    i += 1;
+                }
+            }
+        }
+        Ok(Glob {
+            pattern: pattern.to_string(),
+            tokens,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn is_match(&self, path: &str) -> bool {
+        let text: Vec<char> = path.chars().collect();
+        // reachable[i]: the tokens seen so far can match exactly text[..i].
+        let mut reachable = vec![false; text.len() + 1];
+        reachable[0] = true;
+
+        for token in &self.tokens {
+            let mut next = vec![false; text.len() + 1];
+            for i in 0..=text.len() {
+                if !reachable[i] {
+                    continue;
+                }
+                match token {
+                    Token::Char(c) => {
+                        if text.get(i) == Some(c) {
+                            next[i + 1] = true;
+                        }
+                    }
+                    Token::AnyChar => {
+                        if text.get(i).is_some_and(|&c| c != '/') {
+                            next[i + 1] = true;
+                        }
+                    }
+                    Token::Class { ranges, negated } => {
+                        if let Some(&c) = text.get(i) {
+                            let hit = ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi);
+                            if c != '/' && hit != *negated {
+                                next[i + 1] = true;
+                            }
+                        }
+                    }
+                    Token::Star => {
+                        next[i] = true;
+                        for j in i..text.len() {
+                            if text[j] == '/' {
+                                break;
+                            }
+                            next[j + 1] = true;
+                        }
+                    }
+                    Token::AnyDirs => {
+                        next[i] = true;
+                        for j in i..text.len() {
+                            if text[j] == '/' {
+                                next[j + 1] = true;
+                            }
+                        }
+                    }
+                    Token::AnyPath => {
+                        next[i..].iter_mut().for_each(|r| *r = true);
+                        break;
+                    }
+                }
+            }
+            reachable = next;
+        }
+
+        reachable[text.len()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        Glob::new(pattern).unwrap().is_match(path)
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("*.rs", "lib.rs"));
+        assert!(!matches("*.rs", "src/lib.rs"));
+        assert!(matches("src/*.rs", "src/lib.rs"));
+        assert!(matches("?ib.rs", "lib.rs"));
+        assert!(!matches("?ib.rs", "/ib.rs"));
+        assert!(!matches("[a-m]*.[!t]xt", "poem.txt"));
+        assert!(matches("[a-p]*.t[!a]t", "poem.txt"));
+        assert!(matches("\\*.md", "*.md"));
+    }
+
+    #[test]
+    fn double_star() {
+        assert!(matches("**/target", "target"));
+        assert!(matches("**/target", "a/b/target"));
+        assert!(matches("target/**", "target/debug/minigrep"));
+        assert!(!matches("target/**", "target"));
+        assert!(matches("src/**/mod.rs", "src/mod.rs"));
+        assert!(matches("src/**/mod.rs", "src/a/b/mod.rs"));
+        assert!(!matches("src/**/mod.rs", "lib/a/mod.rs"));
+        assert!(matches("a**b", "aXXb"));
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Glob::new("[abc").is_err());
+        assert!(Glob::new("abc\\").is_err());
+        assert!(Glob::new("[z-a]").is_err());
+    }
+}
@@ -0,0 +1,664 @@
+// gzip decompression (RFC 1952) on top of a DEFLATE decoder (RFC 1951), so
+// compressed logs can be searched without unpacking them first.
+//
+// Decompression streams: output is produced as the caller reads it, and
+// only the last 32 KiB of it (the furthest back a DEFLATE match can reach)
+// is kept. Huffman codes are decoded through a lookup table indexed by the
+// next few bits of input, so each symbol costs one table lookup. Files made
+// of several gzip members back to back (as `cat a.gz b.gz` produces) decode
+// as one stream, and each member's CRC-32 and length are checked.
+
+use std::io::{self, BufRead, BufReader, Read};
+
+pub const MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+const WINDOW_SIZE: usize = 1 << 15;
+const MAX_CODE_BITS: u32 = 15;
+
+// Base values and extra bits for length symbols 257..=285 and distance
+// symbols 0..=29.
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// The order code length code lengths are sent in.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+// Header flags.
+const FHCRC: u8 = 1 << 1;
+const FEXTRA: u8 = 1 << 2;
+const FNAME: u8 = 1 << 3;
+const FCOMMENT: u8 = 1 << 4;
+const FRESERVED: u8 = 0b1110_0000;
+
+pub fn is_gzip(bytes: &[u8]) -> bool {
+    bytes.starts_with(&MAGIC)
+}
+
+/// `reader` as it is, or decompressed if it holds gzip data (judged by its
+/// first bytes) or `force` is set.
+pub fn maybe_decompress<'a, R: BufRead + 'a>(
+    mut reader: R,
+    force: bool,
+) -> io::Result<Box<dyn BufRead + 'a>> {
+    if force || is_gzip(reader.fill_buf()?) {
+        Ok(Box::new(BufReader::new(GzDecoder::new(reader))))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("gzip: {msg}"))
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "gzip: unexpected end of data")
+}
+
+/// Reads the decompressed contents of a gzip stream.
+pub struct GzDecoder<R> {
+    inflate: Inflate<R>,
+    member: Member,
+    crc: u32,
+    size: u32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Member {
+    // Before the header of the first member, or of a following one.
+    Start { first: bool },
+    Body,
+    Done,
+}
+
+impl<R: BufRead> GzDecoder<R> {
+    pub fn new(reader: R) -> GzDecoder<R> {
+        GzDecoder {
+            inflate: Inflate::new(reader),
+            member: Member::Start { first: true },
+            crc: 0,
+            size: 0,
+        }
+    }
+
+    // Returns false when there's no further member.
+    fn read_header(&mut self, first: bool) -> io::Result<bool> {
+        let bits = &mut self.inflate.bits;
+        if first {
+            if [bits.byte()?, bits.byte()?] != MAGIC {
+                return Err(invalid("not in gzip format"));
+            }
+        } else {
+            // Like gzip(1), ignore trailing garbage after a member, such as
+            // padding, however short.
+            for magic in MAGIC {
+                if bits.at_end()? || bits.byte()? != magic {
+                    return Ok(false);
+                }
+            }
+        }
+        if bits.byte()? != 8 {
+            return Err(invalid("unknown compression method"));
+        }
+        let flags = bits.byte()?;
+        if flags & FRESERVED != 0 {
+            return Err(invalid("reserved header flags set"));
+        }
+        // Modification time, extra flags and OS.
+        bits.skip(6)?;
+        if flags & FEXTRA != 0 {
+            let len = u16::from_le_bytes([bits.byte()?, bits.byte()?]);
+            bits.skip(usize::from(len))?;
+        }
+        for flag in [FNAME, FCOMMENT] {
+            if flags & flag != 0 {
+                // Zero-terminated.
+                while bits.byte()? != 0 {}
+            }
+        }
+        if flags & FHCRC != 0 {
+            bits.skip(2)?;
+        }
+        Ok(true)
+    }
+
+    fn read_trailer(&mut self) -> io::Result<()> {
+        let bits = &mut self.inflate.bits;
+        bits.align();
+        let mut word = || -> io::Result<u32> {
+            let mut bytes = [0; 4];
+            for byte in &mut bytes {
+                *byte = bits.byte()?;
+            }
+            Ok(u32::from_le_bytes(bytes))
+        };
+        if word()? != self.crc {
+            return Err(invalid("CRC mismatch, data is corrupt"));
+        }
+        // The length is only stored modulo 2^32.
+        if word()? != self.size {
+            return Err(invalid("length mismatch, data is corrupt"));
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for GzDecoder<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if out.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            match self.member {
+                Member::Start { first } => {
+                    if !self.read_header(first)? {
+                        self.member = Member::Done;
+                        continue;
+                    }
+                    self.inflate.reset();
+                    self.crc = 0;
+                    self.size = 0;
+                    self.member = Member::Body;
+                }
+                Member::Body => {
+                    let n = self.inflate.read(out)?;
+                    if n > 0 {
+                        self.crc = crc32_update(self.crc, &out[..n]);
+                        self.size = self.size.wrapping_add(n as u32);
+                        return Ok(n);
+                    }
+                    self.read_trailer()?;
+                    self.member = Member::Start { first: false };
+                }
+                Member::Done => return Ok(0),
+            }
+        }
+    }
+}
+
+// Bits of a byte stream, least significant first. Bytes are taken from the
+// reader only as they're needed, so nothing past the end of the DEFLATE data
+// is consumed except what `peek` pulled into `buf`, which `byte` hands back.
+struct Bits<R> {
+    inner: R,
+    buf: u64,
+    count: u32,
+}
+
+impl<R: BufRead> Bits<R> {
+    // Tries to have at least `n` bits buffered; fewer at the end of input.
+    fn fill(&mut self, n: u32) -> io::Result<()> {
+        while self.count < n {
+            let Some(&byte) = self.inner.fill_buf()?.first() else {
+                break;
+            };
+            self.inner.consume(1);
+            self.buf |= u64::from(byte) << self.count;
+            self.count += 8;
+        }
+        Ok(())
+    }
+
+    // The next `n` bits without consuming them, padded with zeros past the
+    // end of input.
+    fn peek(&mut self, n: u32) -> io::Result<u32> {
+        self.fill(n)?;
+        Ok((self.buf & ((1 << n) - 1)) as u32)
+    }
+
+    fn consume(&mut self, n: u32) -> io::Result<()> {
+        if self.count < n {
+            return Err(truncated());
+        }
+        self.buf >>= n;
+        self.count -= n;
+        Ok(())
+    }
+
+    fn bits(&mut self, n: u32) -> io::Result<u32> {
+        let value = self.peek(n)?;
+        self.consume(n)?;
+        Ok(value)
+    }
+
+    // Drops the bits left in a partly read byte.
+    fn align(&mut self) {
+        let partial = self.count % 8;
+        self.buf >>= partial;
+        self.count -= partial;
+    }
+
+    // A whole byte; only valid on a byte boundary.
+    fn byte(&mut self) -> io::Result<u8> {
+        Ok(self.bits(8)? as u8)
+    }
+
+    fn skip(&mut self, n: usize) -> io::Result<()> {
+        for _ in 0..n {
+            self.byte()?;
+        }
+        Ok(())
+    }
+
+    fn at_end(&mut self) -> io::Result<bool> {
+        Ok(self.count == 0 && self.inner.fill_buf()?.is_empty())
+    }
+}
+
+// A canonical Huffman code as a lookup table: indexed by the next `bits`
+// bits of input, each entry holds the symbol and its code length.
+struct Huffman {
+    table: Vec<(u16, u8)>,
+    bits: u32,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> io::Result<Huffman> {
+        let bits = u32::from(lengths.iter().copied().max().unwrap_or(0)).max(1);
+        let mut count = [0u32; MAX_CODE_BITS as usize + 1];
+        for &len in lengths {
+            count[usize::from(len)] += 1;
+        }
+        count[0] = 0;
+
+        // The first code of each length.
+        let mut next = [0u32; MAX_CODE_BITS as usize + 2];
+        let mut code = 0;
+        for len in 1..=MAX_CODE_BITS as usize {
+            code = (code + count[len - 1]) << 1;
+            next[len] = code;
+            if count[len] > 0 && code + count[len] > 1 << len {
+                return Err(invalid("over-subscribed Huffman code"));
+            }
+        }
+
+        // Codes are sent most significant bit first, so the table is
+        // indexed by their reversed bits; shorter codes fill every entry
+        // that starts with them.
+        let mut table = vec![(0, 0); 1 << bits];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len == 0 {
+                continue;
+            }
+            let len = u32::from(len);
+            let code = next[len as usize];
+            next[len as usize] += 1;
+            let reversed = code.reverse_bits() >> (32 - len);
+            for index in (reversed as usize..table.len()).step_by(1 << len) {
+                table[index] = (symbol as u16, len as u8);
+            }
+        }
+        Ok(Huffman { table, bits })
+    }
+
+    fn decode<R: BufRead>(&self, input: &mut Bits<R>) -> io::Result<u16> {
+        let (symbol, len) = self.table[input.peek(self.bits)? as usize];
+        if len == 0 {
+            // Past the end of input, or a code that was never assigned.
+            return Err(if input.count < self.bits {
+                truncated()
+            } else {
+                invalid("invalid Huffman code")
+            });
+        }
+        input.consume(u32::from(len))?;
+        Ok(symbol)
+    }
+}
+
+enum Block {
+    // Between blocks: the next thing to read is a block header.
+    Header,
+    Stored {
+        remaining: usize,
+    },
+    Codes {
+        literals: Huffman,
+        distances: Huffman,
+    },
+    Done,
+}
+
+// A raw DEFLATE stream.
+struct Inflate<R> {
+    bits: Bits<R>,
+    block: Block,
+    last_block: bool,
+    window: Vec<u8>,
+    // Bytes produced so far.
+    total: usize,
+    // A back-reference that didn't fit in the caller's buffer.
+    copy_len: usize,
+    copy_distance: usize,
+}
+
+impl<R: BufRead> Inflate<R> {
+    fn new(inner: R) -> Inflate<R> {
+        Inflate {
+            bits: Bits {
+                inner,
+                buf: 0,
+                count: 0,
+            },
+            block: Block::Header,
+            last_block: false,
+            window: vec![0; WINDOW_SIZE],
+            total: 0,
+            copy_len: 0,
+            copy_distance: 0,
+        }
+    }
+
+    // Ready for another stream.
+    fn reset(&mut self) {
+        self.block = Block::Header;
+        self.last_block = false;
+        self.total = 0;
+        self.copy_len = 0;
+    }
+
+    fn emit(&mut self, byte: u8, out: &mut [u8], n: &mut usize) {
+        self.window[self.total % WINDOW_SIZE] = byte;
+        self.total += 1;
+        out[*n] = byte;
+        *n += 1;
+    }
+
+    fn end_block(&mut self) {
+        self.block = if self.last_block {
+            Block::Done
+        } else {
+            Block::Header
+        };
+    }
+
+    // Fills `out` as far as possible; 0 once the stream has ended.
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let mut n = 0;
+        while n < out.len() {
+            if self.copy_len > 0 {
+                let byte = self.window[(self.total - self.copy_distance) % WINDOW_SIZE];
+                self.emit(byte, out, &mut n);
+                self.copy_len -= 1;
+                continue;
+            }
+            match &self.block {
+                Block::Header => self.read_block_header()?,
+                Block::Stored { remaining: 0 } => self.end_block(),
+                Block::Stored { remaining } => {
+                    let remaining = remaining - 1;
+                    let byte = self.bits.byte()?;
+                    self.emit(byte, out, &mut n);
+                    self.block = Block::Stored { remaining };
+                }
+                Block::Codes {
+                    literals,
+                    distances,
+                } => {
+                    let symbol = literals.decode(&mut self.bits)?;
+                    match symbol {
+                        0..=255 => self.emit(symbol as u8, out, &mut n),
+                        256 => self.end_block(),
+                        257..=285 => {
+                            let i = usize::from(symbol - 257);
+                            let extra = self.bits.bits(u32::from(LENGTH_EXTRA[i]))?;
+                            let len = usize::from(LENGTH_BASE[i]) + extra as usize;
+
+                            let i = usize::from(distances.decode(&mut self.bits)?);
+                            if i >= DISTANCE_BASE.len() {
+                                return Err(invalid("invalid distance code"));
+                            }
+                            let extra = self.bits.bits(u32::from(DISTANCE_EXTRA[i]))?;
+                            let distance = usize::from(DISTANCE_BASE[i]) + extra as usize;
+                            if distance > self.total.min(WINDOW_SIZE) {
+                                return Err(invalid("distance too far back"));
+                            }
+                            self.copy_len = len;
+                            self.copy_distance = distance;
+                        }
+                        _ => return Err(invalid("invalid length code")),
+                    }
+                }
+                Block::Done => break,
+            }
+        }
+        Ok(n)
+    }
+
+    fn read_block_header(&mut self) -> io::Result<()> {
+        self.last_block = self.bits.bits(1)? == 1;
+        self.block = match self.bits.bits(2)? {
+            0 => {
+                self.bits.align();
+                let len = self.bits.bits(16)?;
+                let nlen = self.bits.bits(16)?;
+                if len != !nlen & 0xffff {
+                    return Err(invalid("corrupt stored block length"));
+                }
+                Block::Stored {
+                    remaining: len as usize,
+                }
+            }
+            1 => {
+                let mut lengths = [0; 288];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..].fill(8);
+                Block::Codes {
+                    literals: Huffman::new(&lengths)?,
+                    distances: Huffman::new(&[5; 30])?,
+                }
+            }
+            2 => self.read_dynamic_codes()?,
+            _ => return Err(invalid("invalid block type")),
+        };
+        Ok(())
+    }
+
+    fn read_dynamic_codes(&mut self) -> io::Result<Block> {
+        let literal_count = self.bits.bits(5)? as usize + 257;
+        let distance_count = self.bits.bits(5)? as usize + 1;
+        let code_length_count = self.bits.bits(4)? as usize + 4;
+        if literal_count > 286 || distance_count > 30 {
+            return Err(invalid("too many length or distance codes"));
+        }
+
+        let mut code_lengths = [0; 19];
+        for &i in &CODE_LENGTH_ORDER[..code_length_count] {
+            code_lengths[i] = self.bits.bits(3)? as u8;
+        }
+        let code_lengths = Huffman::new(&code_lengths)?;
+
+        // Literal/length and distance code lengths are sent as one list,
+        // run-length encoded.
+        let mut lengths = Vec::with_capacity(literal_count + distance_count);
+        while lengths.len() < literal_count + distance_count {
+            let (value, repeat) = match code_lengths.decode(&mut self.bits)? {
+                len @ 0..=15 => (len as u8, 1),
+                16 => {
+                    let previous = *lengths
+                        .last()
+                        .ok_or_else(|| invalid("repeat with no previous length"))?;
+                    (previous, 3 + self.bits.bits(2)?)
+                }
+                17 => (0, 3 + self.bits.bits(3)?),
+                _ => (0, 11 + self.bits.bits(7)?),
+            };
+            if lengths.len() + repeat as usize > literal_count + distance_count {
+                return Err(invalid("too many code lengths"));
+            }
+            lengths.extend(std::iter::repeat_n(value, repeat as usize));
+        }
+        if lengths[256] == 0 {
+            return Err(invalid("no end-of-block code"));
+        }
+
+        Ok(Block::Codes {
+            literals: Huffman::new(&lengths[..literal_count])?,
+            distances: Huffman::new(&lengths[literal_count..])?,
+        })
+    }
+}
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// The CRC-32 (as used by gzip and zip) of `crc`'s data followed by `bytes`.
+pub fn crc32_update(crc: u32, bytes: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in bytes {
+        crc = CRC_TABLE[((crc ^ u32::from(byte)) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `gzip.compress(..., mtime=0)` output: a stored block and a block with
+    // the fixed Huffman codes.
+    const STORED: [u8; 53] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x03, 0x01, 0x1e, 0x00, 0xe1, 0xff,
+        0x52, 0x75, 0x73, 0x74, 0x3a, 0x0a, 0x73, 0x61, 0x66, 0x65, 0x2c, 0x20, 0x66, 0x61, 0x73,
+        0x74, 0x2c, 0x20, 0x70, 0x72, 0x6f, 0x64, 0x75, 0x63, 0x74, 0x69, 0x76, 0x65, 0x2e, 0x0a,
+        0xe1, 0xb1, 0xff, 0xcb, 0x1e, 0x00, 0x00, 0x00,
+    ];
+    const FIXED: [u8; 44] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x0b, 0xc8, 0x4c, 0xce, 0x56,
+        0x28, 0xc9, 0x28, 0x4a, 0x4d, 0xd5, 0xe3, 0x0a, 0x40, 0x62, 0xbb, 0x94, 0x26, 0x97, 0x28,
+        0x94, 0x24, 0x16, 0x00, 0x99, 0x00, 0x1b, 0xc2, 0x93, 0x6a, 0x23, 0x00, 0x00, 0x00,
+    ];
+    // `gzip -9 data/poem.txt`: dynamic Huffman codes, with the file name in
+    // the header.
+    const POEM: &[u8] = include_bytes!("../data/poem.txt.gz");
+
+    fn decompress(data: &[u8]) -> io::Result<String> {
+        let mut text = String::new();
+        GzDecoder::new(data).read_to_string(&mut text)?;
+        Ok(text)
+    }
+
+    #[test]
+    fn block_types() {
+        assert_eq!(
+            "Rust:\nsafe, fast, productive.\n",
+            decompress(&STORED).unwrap()
+        );
+        assert_eq!(
+            "Pick three.\nPick three.\nDuct tape.\n",
+            decompress(&FIXED).unwrap()
+        );
+        assert_eq!(include_str!("../data/poem.txt"), decompress(POEM).unwrap());
+    }
+
+    #[test]
+    fn multiple_members() {
+        let mut data = STORED.to_vec();
+        data.extend_from_slice(&FIXED);
+        data.extend_from_slice(POEM);
+        let text = decompress(&data).unwrap();
+        assert!(text.starts_with("Rust:\nsafe, fast, productive.\nPick three.\n"));
+        assert!(text.ends_with(include_str!("../data/poem.txt")));
+
+        // Trailing padding after the last member is ignored, down to a
+        // single byte, and even if it starts like another member.
+        for padding in [&[0][..], &[0; 4], &[MAGIC[0]], &[0x1f, 0x00, 0x00]] {
+            let mut padded = data.clone();
+            padded.extend_from_slice(padding);
+            assert_eq!(text, decompress(&padded).unwrap(), "{padding:?}");
+        }
+    }
+
+    #[test]
+    fn small_reads() {
+        // Back-references that straddle the caller's buffer.
+        let mut decoder = GzDecoder::new(POEM);
+        let mut text = Vec::new();
+        let mut buf = [0; 3];
+        loop {
+            let n = decoder.read(&mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            text.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(include_bytes!("../data/poem.txt").as_slice(), text);
+    }
+
+    #[test]
+    fn corrupt_data() {
+        assert!(decompress(b"plain text").is_err());
+
+        let mut bad_crc = FIXED;
+        bad_crc[36] ^= 1;
+        assert!(decompress(&bad_crc).is_err());
+
+        let err = decompress(&POEM[..POEM.len() / 2]).unwrap_err();
+        assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+
+        let mut bad_block = STORED;
+        bad_block[13] ^= 1;
+        assert!(decompress(&bad_block).is_err());
+    }
+
+    #[test]
+    fn crc32() {
+        assert_eq!(0xcbf4_3926, crc32_update(0, b"123456789"));
+        assert_eq!(
+            crc32_update(0, b"123456789"),
+            crc32_update(crc32_update(0, b"1234"), b"56789")
+        );
+    }
+
+    #[test]
+    fn detects_gzip() {
+        let mut text = String::new();
+        maybe_decompress(POEM, false)
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(include_str!("../data/poem.txt"), text);
+
+        text.clear();
+        maybe_decompress(&b"Duct tape."[..], false)
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!("Duct tape.", text);
+    }
+}
@@ -1,60 +1,512 @@
-use std::fs;
-use std::env;
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
 
-pub struct Config {
-    pub query: String,
-    pub file_path: String,
-    pub ignore_case: bool,
+mod aho_corasick;
+mod config;
+mod edit;
+mod error;
+pub mod fold;
+mod follow;
+pub mod fuzzy;
+pub mod glob;
+pub mod gzip;
+mod matcher;
+pub mod memmem;
+mod parallel;
+mod printer;
+mod rcfile;
+pub mod regex;
+mod replace;
+mod searcher;
+mod types;
+pub mod walk;
+
+pub use config::{ColorChoice, Config, ConfigError, USAGE, VERSION};
+pub use error::{MinigrepError, Status};
+use follow::Follower;
+use printer::Printer;
+use regex::Regex;
+pub use searcher::{FileSummary, Hit, Hits, Searcher, Sink, SinkContext, SinkMatch, Stats};
+
+// What grep calls stdin when it has to name it.
+const STDIN_NAME: &str = "(standard input)";
+
+// How often --follow looks for more input.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+pub fn run(config: Config) -> Result<Status, MinigrepError> {
+    /* let contents = fs::read_to_string(config.file_path) // std::io::Result<String>
+        .expect("Should have been able to read the file"); // Returns the String or panics.
+
+    println!("With text:\n{contents}");
+    */
+
+    let started = Instant::now();
+    let searcher = Searcher::new(&config)?;
+    let printer = Printer::new(&config);
+    if config.follow {
+        return follow(&config, &searcher, &printer, &mut io::stdout().lock());
+    }
+    let mut failed = false;
+    let jobs = collect_jobs(&config, &mut failed)?;
+    let threads = match config.threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    };
+
+    let mut matched = false;
+    let mut stats = Stats::default();
+    // Inputs that can't be read are reported and skipped; anything else
+    // (an error writing the output) ends the run.
+    let mut finish = |result: Result<Option<FileSummary>, MinigrepError>| match result {
+        Ok(summary) => {
+            if let Some(summary) = summary {
+                matched |= summary.matched_lines > 0;
+                stats.add(&summary);
+            }
+            Ok(())
+        }
+        Err(MinigrepError::Io { path: None, source }) => Err(source),
+        Err(err) => {
+            eprintln!("minigrep: {err}");
+            failed = true;
+            Ok(())
+        }
+    };
+
+    // --fuzzy lists the closest lines of all the inputs first, so nothing
+    // can be printed until every input has been searched.
+    let rank = searcher.ranks()
+        && jobs.len() > 1
+        && !config.in_place
+        && !config.count
+        && !config.count_matches
+        && !config.files_with_matches;
+    if rank {
+        let mut hits = Vec::new();
+        // Results are finished in the order of the jobs.
+        let mut jobs_done = jobs.iter();
+        parallel::for_each_ordered(
+            &jobs,
+            threads,
+            &mut io::sink(),
+            |job, _| {
+                let mut found = Hits::default();
+                let result = job
+                    .search(&config, &searcher, &mut found)
+                    .map_err(|err| MinigrepError::io(&job.name, err));
+                (result, found.0)
+            },
+            |(result, found)| {
+                let job = jobs_done.next().unwrap();
+                hits.extend(found.into_iter().map(|hit| (job, hit)));
+                finish(result)
+            },
+        )?;
+        // A stable sort, so equally close lines stay in input order.
+        hits.sort_by_key(|(_, hit)| hit.distance);
+        let mut out = io::stdout().lock();
+        for (job, hit) in &hits {
+            searcher.report_hit(&job.name, hit, &mut printer.sink(&mut out, job.with_path))?;
+        }
+    } else if threads == 1 || jobs.len() == 1 {
+        // Nothing to overlap: stream straight to stdout.
+        let mut out = io::stdout().lock();
+        for job in &jobs {
+            finish(job.run(&config, &searcher, &printer, &mut out))?;
+        }
+    } else {
+        // The workers take turns writing, so stdout can't stay locked.
+        parallel::for_each_ordered(
+            &jobs,
+            threads,
+            &mut io::stdout(),
+            |job, buf| job.run(&config, &searcher, &printer, buf),
+            finish,
+        )?;
+    }
+    let mut out = io::stdout().lock();
+    if config.stats {
+        printer.stats(&mut out, &stats, started.elapsed())?;
+    }
+    out.flush()?;
+
+    Ok(if failed {
+        Status::Error
+    } else if matched {
+        Status::Matched
+    } else {
+        Status::NoMatch
+    })
+}
+
+// --follow: searches each file, then whatever is added to it, until the
+// process is interrupted.
+fn follow(
+    config: &Config,
+    searcher: &Searcher,
+    printer: &Printer,
+    out: &mut impl Write,
+) -> Result<Status, MinigrepError> {
+    let with_path = config.paths.len() > 1;
+    let mut followers = Vec::new();
+    for operand in &config.paths {
+        let follower = Follower::open(Path::new(operand), operand)
+            .map_err(|err| MinigrepError::io(operand, err))?;
+        followers.push((operand, follower));
+    }
+
+    loop {
+        for (operand, follower) in &mut followers {
+            let mut out = Output {
+                inner: &mut *out,
+                failed: false,
+            };
+            let result = follower.poll(searcher, &mut printer.sink(&mut out, with_path));
+            result.map_err(|err| {
+                // Blame the output rather than the file when writing failed.
+                if out.failed {
+                    MinigrepError::from(err)
+                } else {
+                    MinigrepError::io(operand, err)
+                }
+            })?;
+        }
+        out.flush()?;
+        thread::sleep(POLL_INTERVAL);
+    }
 }
 
-impl Config {
-    pub fn build(mut args: impl Iterator<Item = String>) -> Result<Config, &'static str> {
-        args.next(); // program name (after release, binary name) is useless
+// One input to search: a file, or stdin when `path` is `None`.
+struct Job {
+    path: Option<PathBuf>,
+    name: String,
+    with_path: bool,
+    // Files found by walking a directory are skipped when they look binary;
+    // files named on the command line are always searched.
+    skip_binary: bool,
+}
 
-        let query = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a query string"),
+impl Job {
+    // Searches (or edits) the input, and sums up what was selected (or
+    // changed). None if it was skipped as binary.
+    fn run(
+        &self,
+        config: &Config,
+        searcher: &Searcher,
+        printer: &Printer,
+        out: &mut impl Write,
+    ) -> Result<Option<FileSummary>, MinigrepError> {
+        let mut out = Output {
+            inner: out,
+            failed: false,
+        };
+        let result = match &self.path {
+            // Config::build makes sure --in-place only gets files.
+            Some(path) if config.in_place => self.edit(config, searcher, path, &mut out),
+            _ => self
+                .search(
+                    config,
+                    searcher,
+                    &mut printer.sink(&mut out, self.with_path),
+                )
+                .map_err(|err| MinigrepError::io(&self.name, err)),
         };
+        // Blame the output rather than the input when writing it failed.
+        result.map_err(|err| match err {
+            MinigrepError::Io { source, .. } if out.failed => source.into(),
+            err => err,
+        })
+    }
+
+    fn edit(
+        &self,
+        config: &Config,
+        searcher: &Searcher,
+        path: &Path,
+        out: &mut impl Write,
+    ) -> Result<Option<FileSummary>, MinigrepError> {
+        let started = Instant::now();
+        let contents = fs::read(path).map_err(|err| MinigrepError::io(&self.name, err))?;
+        if self.skip_binary && walk::is_binary(&contents) {
+            return Ok(None);
+        }
+        let contents = String::from_utf8(contents).map_err(|_| MinigrepError::Encoding {
+            path: self.name.clone(),
+        })?;
+        let changed = edit::edit_file(searcher, path, &self.name, &contents, config.dry_run, out)
+            .map_err(|err| MinigrepError::io(&self.name, err))?;
+        Ok(Some(FileSummary {
+            matched_lines: changed,
+            bytes_searched: contents.len() as u64,
+            elapsed: started.elapsed(),
+            ..FileSummary::default()
+        }))
+    }
 
-        let file_path = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a file path"),
+    fn search(
+        &self,
+        config: &Config,
+        searcher: &Searcher,
+        sink: &mut impl Sink,
+    ) -> io::Result<Option<FileSummary>> {
+        // Compressed input is searched as the text it decompresses to.
+        let mut reader = match &self.path {
+            Some(path) => {
+                let file = BufReader::new(File::open(path)?);
+                gzip::maybe_decompress(file, config.decompress)?
+            }
+            None => gzip::maybe_decompress(io::stdin().lock(), config.decompress)?,
         };
+        if self.skip_binary && walk::is_binary(reader.fill_buf()?) {
+            return Ok(None);
+        }
+        searcher.search_reader(&self.name, reader, sink).map(Some)
+    }
+}
 
-        let ignore_case = env::var("IGNORE_CASE").is_ok();
+// Passes writes on to the real output, remembering whether one failed.
+struct Output<W> {
+    inner: W,
+    failed: bool,
+}
 
-        Ok(Config {
-            query,
-            file_path,
-            ignore_case,
-        })
+impl<W: Write> Write for Output<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let result = self.inner.write(buf);
+        self.failed |= result.is_err();
+        result
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let result = self.inner.flush();
+        self.failed |= result.is_err();
+        result
     }
 }
 
-pub fn run(config: Config) -> Result<(), Box<dyn std::error::Error>> {
-    /* let contents = fs::read_to_string(config.file_path) // std::io::Result<String>
-        .expect("Should have been able to read the file"); // Returns the String or panics.
+// Parts of a directory that can't be read are reported, and set `failed`,
+// but don't stop the rest being searched.
+fn collect_jobs(config: &Config, failed: &mut bool) -> Result<Vec<Job>, MinigrepError> {
+    // Like grep, name the file on each line once there's more than one.
+    let with_path = config.paths.len() > 1;
+    let mut jobs = Vec::new();
+    let filter = config.walk_filter()?;
 
-    println!("With text:\n{contents}");
-    */
+    for operand in &config.paths {
+        if operand == "-" {
+            jobs.push(Job {
+                path: None,
+                name: STDIN_NAME.to_string(),
+                with_path,
+                skip_binary: false,
+            });
+            continue;
+        }
 
-    let contents = fs::read_to_string(config.file_path)?;
+        let path = Path::new(operand);
+        if path.is_dir() {
+            let walk = walk::walk_filtered(path, &filter);
+            for (path, err) in walk.errors {
+                eprintln!(
+                    "minigrep: {}",
+                    MinigrepError::io(&path.display().to_string(), err)
+                );
+                *failed = true;
+            }
+            for file in walk.files {
+                jobs.push(Job {
+                    name: file.display().to_string(),
+                    path: Some(file),
+                    with_path: true,
+                    skip_binary: true,
+                });
+            }
+        } else {
+            jobs.push(Job {
+                path: Some(path.to_path_buf()),
+                name: operand.clone(),
+                with_path,
+                skip_binary: false,
+            });
+        }
+    }
 
-    let results = if config.ignore_case {
-        search_case_insensitive(&config.query, &contents)
-    } else {
-        search(&config.query, &contents)
-    };
-    for line in results {
-        println!("{line}");
+    Ok(jobs)
+}
+
+/// A line picked by `search_with_context`: either a selected line, or one of
+/// the lines of context around it.
+#[derive(Debug, PartialEq, Eq)]
+pub struct LineMatch<'a> {
+    pub line_number: usize,
+    // The last line `line` covers, which is `line_number` unless it's a
+    // --multiline match.
+    pub end_line_number: usize,
+    pub line: &'a str,
+    pub is_context: bool,
+}
+
+/// Every line for which `is_match` holds, plus up to `before`/`after` lines
+/// of context around each one. Overlapping windows are merged, so no line
+/// appears twice and the records come out in file order.
+pub fn search_with_context<'a, F>(
+    contents: &'a str,
+    before: usize,
+    after: usize,
+    is_match: F,
+) -> Vec<LineMatch<'a>>
+where
+    F: Fn(&str) -> bool,
+{
+    let lines: Vec<&str> = contents.lines().collect();
+    let mut results = Vec::new();
+    // First line that hasn't been emitted yet.
+    let mut next_unemitted = 0;
+    let mut after_left = 0;
+
+    for (i, &line) in lines.iter().enumerate() {
+        if is_match(line) {
+            let start = i.saturating_sub(before).max(next_unemitted);
+            for (j, &line) in lines.iter().enumerate().take(i).skip(start) {
+                results.push(LineMatch {
+                    line_number: j + 1,
+                    end_line_number: j + 1,
+                    line,
+                    is_context: true,
+                });
+            }
+            results.push(LineMatch {
+                line_number: i + 1,
+                end_line_number: i + 1,
+                line,
+                is_context: false,
+            });
+            next_unemitted = i + 1;
+            after_left = after;
+        } else if after_left > 0 {
+            results.push(LineMatch {
+                line_number: i + 1,
+                end_line_number: i + 1,
+                line,
+                is_context: true,
+            });
+            next_unemitted = i + 1;
+            after_left -= 1;
+        }
+    }
+
+    results
+}
+
+/// Streaming counterpart of `search_with_context`: reads `reader` one line at
+/// a time and hands each record to `emit` as soon as it's known, keeping only
+/// the last `before` lines in memory. Invalid UTF-8 is replaced with U+FFFD
+/// instead of failing the whole search. `emit` returns `Ok(false)` to stop
+/// reading early.
+pub fn search_reader<R, F, E>(
+    mut reader: R,
+    before: usize,
+    after: usize,
+    is_match: F,
+    mut emit: E,
+) -> io::Result<()>
+where
+    R: BufRead,
+    F: Fn(&str) -> bool,
+    E: FnMut(LineMatch<'_>) -> io::Result<bool>,
+{
+    let mut buf = Vec::new();
+    let mut previous: VecDeque<(usize, String)> = VecDeque::with_capacity(before);
+    let mut line_number = 0;
+    let mut after_left = 0;
+
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            break;
+        }
+        line_number += 1;
+        // Same line endings as `str::lines`: "\n" or "\r\n".
+        if buf.last() == Some(&b'\n') {
+            buf.pop();
+            if buf.last() == Some(&b'\r') {
+                buf.pop();
+            }
+        }
+        let line = String::from_utf8_lossy(&buf);
+
+        if is_match(&line) {
+            for (line_number, line) in previous.drain(..) {
+                let record = LineMatch {
+                    line_number,
+                    end_line_number: line_number,
+                    line: &line,
+                    is_context: true,
+                };
+                if !emit(record)? {
+                    return Ok(());
+                }
+            }
+            let record = LineMatch {
+                line_number,
+                end_line_number: line_number,
+                line: &line,
+                is_context: false,
+            };
+            if !emit(record)? {
+                return Ok(());
+            }
+            after_left = after;
+        } else if after_left > 0 {
+            let record = LineMatch {
+                line_number,
+                end_line_number: line_number,
+                line: &line,
+                is_context: true,
+            };
+            if !emit(record)? {
+                return Ok(());
+            }
+            after_left -= 1;
+        } else if before > 0 {
+            // Recycle the oldest line's allocation once the window is full.
+            let mut slot = if previous.len() == before {
+                previous.pop_front().unwrap().1
+            } else {
+                String::new()
+            };
+            slot.clear();
+            slot.push_str(&line);
+            previous.push_back((line_number, slot));
+        }
     }
 
     Ok(())
 }
 
 pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    contents.lines()
-        .filter(|line| line.contains(query))
+    if query.is_empty() || query.contains(['\n', '\r']) {
+        return contents
+            .lines()
+            .filter(|line| line.contains(query))
+            .collect();
+    }
+    // Scan the whole text for the query and only look for the lines around
+    // each hit, instead of checking every line.
+    let finder = memmem::Finder::new(query);
+    finder
+        .find_lines(contents.as_bytes())
+        .map(|(start, end)| {
+            let line = &contents[start..end];
+            line.strip_suffix('\r')
+                .filter(|_| end < contents.len())
+                .unwrap_or(line)
+        })
         .collect()
 }
 
@@ -62,9 +514,17 @@ pub fn search_case_insensitive<'a>(
     query: &str,
     contents: &'a str,
 ) -> Vec<&'a str> {
-    let query = query.to_lowercase();
+    // Fold the query once; lines are folded char by char while matching,
+    // without allocating.
+    let query = fold::fold_str(query);
+    contents.lines()
+        .filter(|line| fold::find(line, &query, 0).is_some())
+        .collect()
+}
+
+pub fn search_regex<'a>(regex: &Regex, contents: &'a str) -> Vec<&'a str> {
     contents.lines()
-        .filter(|line| line.to_lowercase().contains(&query))
+        .filter(|line| regex.is_match(line))
         .collect()
 }
 
@@ -85,6 +545,22 @@ Duct tape.";
         assert_eq!(vec!["safe, fast, productive."], search(query, contents));
     }
 
+    #[test]
+    fn literal_lines() {
+        let contents = "Rust:\r\nsafe, fast, productive.\r\nPick three.\nproduct\r";
+        assert_eq!(
+            vec!["safe, fast, productive.", "product\r"],
+            search("duct", contents)
+        );
+        for query in ["", "e.\r", "Rust:\r\n"] {
+            let lines: Vec<&str> = contents
+                .lines()
+                .filter(|line| line.contains(query))
+                .collect();
+            assert_eq!(lines, search(query, contents), "{query:?}");
+        }
+    }
+
     #[test]
     fn case_insensitive() {
         let query = "rUsT";
@@ -99,4 +575,131 @@ Trust me.";
             search_case_insensitive(query, contents)
         );
     }
+
+    #[test]
+    fn case_insensitive_unicode() {
+        let query = "STRASSE";
+        let contents = "\
+Hauptstraße 1
+Bahnhofstrasse 2
+Marktplatz 3";
+
+        assert_eq!(
+            vec!["Hauptstraße 1", "Bahnhofstrasse 2"],
+            search_case_insensitive(query, contents)
+        );
+    }
+
+    #[test]
+    fn context_lines() {
+        let contents = "\
+one
+two match
+three
+four
+five
+six match
+seven
+eight match
+nine
+ten";
+
+        let lines: Vec<(usize, bool)> =
+            search_with_context(contents, 1, 1, |line| line.contains("match"))
+                .iter()
+                .map(|record| (record.line_number, record.is_context))
+                .collect();
+        // 1-3 around "two", then 5-9 merged for "six" and "eight".
+        assert_eq!(
+            vec![
+                (1, true),
+                (2, false),
+                (3, true),
+                (5, true),
+                (6, false),
+                (7, true),
+                (8, false),
+                (9, true),
+            ],
+            lines
+        );
+
+        assert_eq!(
+            vec![LineMatch {
+                line_number: 6,
+                end_line_number: 6,
+                line: "six match",
+                is_context: false
+            }],
+            search_with_context(contents, 0, 0, |line| line.starts_with("six"))
+        );
+    }
+
+    #[test]
+    fn streaming_matches_buffered() {
+        let contents = "one\ntwo match\r\nthree\nfour\nfive\nsix match\nseven\n";
+        let is_match = |line: &str| line.contains("match");
+
+        let mut streamed = Vec::new();
+        search_reader(contents.as_bytes(), 2, 1, is_match, |record| {
+            streamed.push((
+                record.line_number,
+                record.line.to_string(),
+                record.is_context,
+            ));
+            Ok(true)
+        })
+        .unwrap();
+
+        let buffered: Vec<(usize, String, bool)> = search_with_context(contents, 2, 1, is_match)
+            .into_iter()
+            .map(|record| {
+                (
+                    record.line_number,
+                    record.line.to_string(),
+                    record.is_context,
+                )
+            })
+            .collect();
+        assert_eq!(buffered, streamed);
+    }
+
+    #[test]
+    fn streaming_invalid_utf8() {
+        let contents: &[u8] = b"caf\xe9 productive\nsafe\n\xff\xfeduct tape";
+
+        let mut lines = Vec::new();
+        search_reader(
+            contents,
+            0,
+            0,
+            |line| line.contains("duct"),
+            |record| {
+                lines.push(record.line.to_string());
+                Ok(true)
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec!["caf\u{fffd} productive", "\u{fffd}\u{fffd}duct tape"],
+            lines
+        );
+    }
+
+    #[test]
+    fn regex_results() {
+        let regex = Regex::new("^(Pick|Duct) t").unwrap();
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Duct tape.
+Trust me.";
+
+        assert_eq!(
+            vec!["Pick three.", "Duct tape."],
+            search_regex(&regex, contents)
+        );
+    }
 }
@@ -1,20 +1,27 @@
 use std::env;
+use std::process;
 
-use minigrep::Config;
+use minigrep::{Config, ConfigError, Status};
 
 fn main() {
     // dbg!(args);
-    // For now, ignore error handling.
     let config = Config::build(env::args()).unwrap_or_else(|err| {
+        if let ConfigError::HelpRequested | ConfigError::VersionRequested = err {
+            println!("{}", err);
+            process::exit(0);
+        }
         eprintln!("Problem parsing arguments: {}", err);
-        std::process::exit(1);
+        eprintln!("Try 'minigrep --help' for more information.");
+        process::exit(Status::Error.code());
     });
 
-    println!("Searching for {}", config.query);
-    println!("In file {}", config.file_path);
-
-    if let Err(e) = minigrep::run(config) {
-        eprintln!("Application error: {}", e);
-        std::process::exit(1);
+    match minigrep::run(config) {
+        Ok(status) => process::exit(status.code()),
+        // Whoever was reading the output has seen all they wanted.
+        Err(e) if e.is_broken_pipe() => process::exit(0),
+        Err(e) => {
+            eprintln!("Application error: {}", e);
+            process::exit(Status::Error.code());
+        }
     }
 }
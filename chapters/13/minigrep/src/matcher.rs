@@ -0,0 +1,316 @@
+use crate::aho_corasick::AhoCorasick;
+use crate::fold;
+use crate::fuzzy::Fuzzy;
+use crate::memmem::Finder;
+use crate::regex::{is_word_char, Captures, Regex, RegexError};
+use crate::replace::Replacement;
+use crate::Config;
+
+// The patterns, prepared once for whichever kind of matching the config
+// asks for.
+#[derive(Debug)]
+enum Kind {
+    // No patterns at all (e.g. an empty `-f` file): nothing matches.
+    Nothing,
+    Literal(Finder),
+    // Holds the case-folded pattern.
+    CaseInsensitive(Vec<char>),
+    // Several literal patterns.
+    Multi(AhoCorasick),
+    Regex(Regex),
+    // --fuzzy: each pattern with up to K edits.
+    Fuzzy(Vec<Fuzzy>),
+}
+
+#[derive(Debug)]
+pub struct Matcher {
+    kind: Kind,
+    // -w: matches must not touch a word character on either side.
+    word: bool,
+    // -x: matches must cover the whole line.
+    whole_line: bool,
+    // -U: the text searched may hold several lines, so -x looks for line
+    // breaks around a match rather than the ends of the text.
+    multiline: bool,
+}
+
+impl Matcher {
+    pub fn new(config: &Config) -> Result<Matcher, RegexError> {
+        let patterns = &config.patterns;
+        let kind = if let Some(max_edits) = config.fuzzy {
+            let fuzzy = patterns
+                .iter()
+                .map(|pattern| Fuzzy::new(pattern, max_edits, config.ignore_case));
+            Kind::Fuzzy(fuzzy.collect())
+        } else if config.regex && !patterns.is_empty() {
+            // One regex that matches if any of the patterns does.
+            let mut joined = patterns
+                .iter()
+                .map(|pattern| format!("(?:{pattern})"))
+                .collect::<Vec<_>>()
+                .join("|");
+            if config.line_regexp {
+                joined = format!("^(?:{joined})$");
+            }
+            let regex = if config.word_regexp {
+                Regex::build_words(&joined, config.ignore_case, config.multiline)?
+            } else if config.multiline {
+                Regex::build_multi_line(&joined, config.ignore_case)?
+            } else {
+                Regex::build(&joined, config.ignore_case)?
+            };
+            Kind::Regex(regex)
+        } else {
+            match patterns.as_slice() {
+                [] => Kind::Nothing,
+                [pattern] if config.ignore_case => Kind::CaseInsensitive(fold::fold_str(pattern)),
+                [pattern] => Kind::Literal(Finder::new(pattern)),
+                patterns => Kind::Multi(AhoCorasick::new(patterns, config.ignore_case)),
+            }
+        };
+        Ok(Matcher {
+            kind,
+            word: config.word_regexp,
+            whole_line: config.line_regexp,
+            multiline: config.multiline,
+        })
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        match &self.kind {
+            Kind::Fuzzy(_) => self.distance(line).is_some(),
+            _ => self.find_at(line, 0).is_some(),
+        }
+    }
+
+    /// How many edits away from a match `line` is: with --fuzzy the fewest
+    /// for any pattern, otherwise 0 for a match. None if it doesn't match.
+    pub fn distance(&self, line: &str) -> Option<usize> {
+        match &self.kind {
+            Kind::Fuzzy(patterns) => patterns
+                .iter()
+                .filter_map(|pattern| pattern.distance(line))
+                .min(),
+            _ => self.is_match(line).then_some(0),
+        }
+    }
+
+    /// Byte range of the first match in `line` at or after `start`.
+    pub fn find_at(&self, line: &str, start: usize) -> Option<(usize, usize)> {
+        // A regex has -w and -x built in.
+        if (!self.word && !self.whole_line) || matches!(self.kind, Kind::Regex(_)) {
+            return self.find_any(line, start);
+        }
+
+        let accept = |from: usize, to: usize| self.accepts(line, from, to);
+        if let Kind::Multi(ac) = &self.kind {
+            return ac.find_at_where(line, start, accept);
+        }
+
+        // Other matchers only report one candidate per position, so retry
+        // one char further along whenever a candidate is rejected.
+        let mut start = start;
+        loop {
+            let (from, to) = self.find_any(line, start)?;
+            if accept(from, to) {
+                return Some((from, to));
+            }
+            start = from + line[from..].chars().next()?.len_utf8();
+        }
+    }
+
+    /// The single literal pattern, if a line matches exactly when its bytes
+    /// contain it. Lets the searcher scan whole buffers for it rather than
+    /// looking at one line at a time.
+    pub fn literal(&self) -> Option<&Finder> {
+        match &self.kind {
+            Kind::Literal(finder) if !self.word && !self.whole_line => {
+                let needle = finder.needle();
+                // An empty pattern is in every line, line endings are never
+                // part of one, and U+FFFD may stand for invalid bytes.
+                let plain = !needle.is_empty()
+                    && !needle.contains(&b'\n')
+                    && !needle.contains(&b'\r')
+                    && !needle.windows(3).any(|w| w == "\u{fffd}".as_bytes());
+                plain.then_some(finder)
+            }
+            _ => None,
+        }
+    }
+
+    /// Like `find_at`, but with the regex's capture groups (just group 0 for
+    /// literal patterns).
+    pub fn captures_at(&self, line: &str, start: usize) -> Option<Captures> {
+        match &self.kind {
+            Kind::Regex(regex) => regex.captures_at(line, start),
+            _ => {
+                let (from, to) = self.find_at(line, start)?;
+                Some(Captures::whole(from, to))
+            }
+        }
+    }
+
+    /// The `--replace` text for these patterns: capture group references
+    /// are only understood in regex mode.
+    pub fn replacement(&self, template: &str) -> Result<Replacement, RegexError> {
+        match &self.kind {
+            Kind::Regex(regex) => Replacement::parse(template, regex),
+            _ => Ok(Replacement::literal(template)),
+        }
+    }
+
+    // Whether a match at `from..to` satisfies -w and -x.
+    fn accepts(&self, line: &str, from: usize, to: usize) -> bool {
+        if self.whole_line && self.multiline {
+            let rest = &line[to..];
+            let ends_line = rest.is_empty() || rest.starts_with('\n') || rest.starts_with("\r\n");
+            return (from == 0 || line[..from].ends_with('\n')) && ends_line;
+        }
+        if self.whole_line {
+            return from == 0 && to == line.len();
+        }
+        if !self.word {
+            return true;
+        }
+        let before = line[..from].chars().next_back();
+        let after = line[to..].chars().next();
+        !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char)
+    }
+
+    fn find_any(&self, line: &str, start: usize) -> Option<(usize, usize)> {
+        match &self.kind {
+            Kind::Nothing => None,
+            Kind::Literal(finder) => finder
+                .find(&line.as_bytes()[start..])
+                .map(|i| (start + i, start + i + finder.needle().len())),
+            Kind::CaseInsensitive(query) => fold::find(line, query, start),
+            Kind::Multi(ac) => ac.find_at(line, start),
+            Kind::Regex(regex) => regex.find_at(line, start),
+            Kind::Fuzzy(patterns) => patterns
+                .iter()
+                .filter_map(|pattern| pattern.find_at(line, start))
+                .min_by_key(|hit| (hit.start, hit.distance))
+                .map(|hit| (hit.start, hit.end)),
+        }
+    }
+
+    /// Byte ranges of every non-empty, non-overlapping match in `line`.
+    pub fn find_iter(&self, line: &str) -> Vec<(usize, usize)> {
+        let mut matches = Vec::new();
+        let mut start = 0;
+        while let Some((from, to)) = self.find_at(line, start) {
+            if to > from {
+                matches.push((from, to));
+                start = to;
+            } else {
+                // Step over an empty match so the loop makes progress.
+                match line[from..].chars().next() {
+                    Some(c) => start = from + c.len_utf8(),
+                    None => break,
+                }
+            }
+        }
+        matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(patterns: &[&str], configure: impl FnOnce(&mut Config)) -> Matcher {
+        let mut config = Config {
+            patterns: patterns.iter().map(|p| p.to_string()).collect(),
+            ..Config::default()
+        };
+        configure(&mut config);
+        Matcher::new(&config).unwrap()
+    }
+
+    #[test]
+    fn match_offsets() {
+        let literal = matcher(&["duct"], |_| {});
+        assert_eq!(
+            vec![(15, 19), (19, 23)],
+            literal.find_iter("safe, fast, productduct.")
+        );
+
+        // Offsets point into the original line, not its folded form.
+        let insensitive = matcher(&["RUST"], |c| c.ignore_case = true);
+        assert_eq!(
+            vec![(4, 8), (10, 14)],
+            insensitive.find_iter("İ: RuST TRUST")
+        );
+
+        let regex = matcher(&["a*"], |c| c.regex = true);
+        assert_eq!(vec![(1, 3)], regex.find_iter("baab"));
+    }
+
+    #[test]
+    fn multiple_patterns() {
+        let multi = matcher(&["fast", "three", "Duct"], |_| {});
+        assert_eq!(vec![(6, 10)], multi.find_iter("safe, fast, productive."));
+        assert!(multi.is_match("Pick three."));
+        assert!(!multi.is_match("duct tape."));
+
+        let regex = matcher(&["^Pick", "ape\\.$"], |c| c.regex = true);
+        assert_eq!(vec![(0, 4)], regex.find_iter("Pick three."));
+        assert_eq!(vec![(6, 10)], regex.find_iter("Duct tape."));
+
+        assert!(!matcher(&[], |_| {}).is_match("anything"));
+    }
+
+    #[test]
+    fn whole_words_and_lines() {
+        for patterns in [&["duct"][..], &["duct", "tape"][..]] {
+            let word = matcher(patterns, |c| c.word_regexp = true);
+            assert!(!word.is_match("safe, fast, productive."));
+            assert_eq!(Some((8, 12)), word.find_at("product duct", 0));
+        }
+
+        let word = matcher(&["\\w+ct"], |c| {
+            c.regex = true;
+            c.word_regexp = true;
+        });
+        assert_eq!(Some((9, 13)), word.find_at("product_ duct", 0));
+        // A longer alternative is tried when the first one isn't a word.
+        let word = matcher(&["ab|abc"], |c| {
+            c.regex = true;
+            c.word_regexp = true;
+        });
+        assert_eq!(vec![(0, 3)], word.find_iter("abc"));
+        assert_eq!(vec![(5, 8)], word.find_iter("abcd abc"));
+
+        let line = matcher(&["Pick", "Pick three."], |c| c.line_regexp = true);
+        assert!(line.is_match("Pick three."));
+        assert!(!line.is_match("Pick three. Now."));
+
+        let line = matcher(&["pick.*"], |c| {
+            c.regex = true;
+            c.line_regexp = true;
+            c.ignore_case = true;
+        });
+        assert!(line.is_match("Pick three."));
+        assert!(!line.is_match("I Pick three."));
+    }
+
+    #[test]
+    fn across_lines() {
+        let text = "Pick three.\r\nDuct tape.\nTrust me.";
+        let literal = matcher(&["three.\r\nDuct"], |c| c.multiline = true);
+        assert_eq!(vec![(5, 17)], literal.find_iter(text));
+
+        // -x wants whole lines, not the whole text.
+        let line = matcher(&["Duct tape.", "Trust"], |c| {
+            c.multiline = true;
+            c.line_regexp = true;
+        });
+        assert_eq!(vec![(13, 23)], line.find_iter(text));
+        let line = matcher(&["\\w+ tape\\.\\n.*"], |c| {
+            c.multiline = true;
+            c.line_regexp = true;
+            c.regex = true;
+        });
+        assert_eq!(vec![(13, 33)], line.find_iter(text));
+    }
+}
@@ -0,0 +1,363 @@
+// Fast byte search for literal patterns, so a whole buffer can be scanned
+// for the pattern at once instead of line by line.
+//
+// `memchr` and friends look at eight bytes at a time with plain integer
+// operations ("SWAR"), which the compiler turns into wide loads and which
+// needs no `unsafe` or platform intrinsics. `Finder` is the Two-Way
+// algorithm of Crochemore and Perrin: linear time and constant space
+// whatever the pattern, with `memchr` used to skip to the next place the
+// pattern's rarest byte (going by typical text) occurs.
+
+const LO: u64 = 0x0101_0101_0101_0101;
+const HI: u64 = 0x8080_8080_8080_8080;
+const WORD: usize = 8;
+
+// A word with `byte` in every byte.
+fn splat(byte: u8) -> u64 {
+    LO * u64::from(byte)
+}
+
+// Whether any byte of `word` is zero.
+fn has_zero(word: u64) -> bool {
+    word.wrapping_sub(LO) & !word & HI != 0
+}
+
+fn word_at(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(bytes[..WORD].try_into().unwrap())
+}
+
+/// Offset of the first `byte` in `haystack`.
+pub fn memchr(byte: u8, haystack: &[u8]) -> Option<usize> {
+    let repeated = splat(byte);
+    let mut offset = 0;
+    for chunk in haystack.chunks_exact(2 * WORD) {
+        let found =
+            has_zero(word_at(chunk) ^ repeated) || has_zero(word_at(&chunk[WORD..]) ^ repeated);
+        if found {
+            break;
+        }
+        offset += 2 * WORD;
+    }
+    haystack[offset..]
+        .iter()
+        .position(|&b| b == byte)
+        .map(|i| offset + i)
+}
+
+/// Offset of the last `byte` in `haystack`.
+pub fn memrchr(byte: u8, haystack: &[u8]) -> Option<usize> {
+    let repeated = splat(byte);
+    let mut end = haystack.len();
+    for chunk in haystack.rchunks_exact(2 * WORD) {
+        let found =
+            has_zero(word_at(chunk) ^ repeated) || has_zero(word_at(&chunk[WORD..]) ^ repeated);
+        if found {
+            break;
+        }
+        end -= 2 * WORD;
+    }
+    haystack[..end].iter().rposition(|&b| b == byte)
+}
+
+/// How many times `byte` occurs in `haystack`.
+pub fn count(byte: u8, haystack: &[u8]) -> usize {
+    let repeated = splat(byte);
+    let low7 = !HI;
+    let mut chunks = haystack.chunks_exact(WORD);
+    let mut total = 0;
+    for chunk in &mut chunks {
+        let x = word_at(chunk) ^ repeated;
+        // Exactly the high bit of each zero byte (no borrows between bytes,
+        // unlike `has_zero`).
+        let zeros = !(((x & low7) + low7) | x) & HI;
+        total += zeros.count_ones() as usize;
+    }
+    total + chunks.remainder().iter().filter(|&&b| b == byte).count()
+}
+
+/// A needle prepared for searching with Two-Way.
+#[derive(Debug, Clone)]
+pub struct Finder {
+    needle: Vec<u8>,
+    // Where the needle is split into a left and a right half.
+    critical: usize,
+    period: usize,
+    // Whether the needle repeats with `period`, in which case the part of
+    // it already known to match is remembered between shifts.
+    periodic: bool,
+    // Position of the byte that's scanned for before trying a match.
+    rare: usize,
+}
+
+impl Finder {
+    pub fn new(needle: &str) -> Finder {
+        let needle = needle.as_bytes().to_vec();
+        let (critical, period) = critical_factorization(&needle);
+        let periodic = period + critical <= needle.len()
+            && needle[..critical] == needle[period..period + critical];
+        let period = if periodic {
+            period
+        } else {
+            // Any shift up to this long is safe.
+            critical.max(needle.len() - critical) + 1
+        };
+        let rare = (0..needle.len())
+            .min_by_key(|&i| frequency(needle[i]))
+            .unwrap_or(0);
+        Finder {
+            needle,
+            critical,
+            period,
+            periodic,
+            rare,
+        }
+    }
+
+    pub fn needle(&self) -> &[u8] {
+        &self.needle
+    }
+
+    /// Offset of the first occurrence of the needle in `haystack`.
+    pub fn find(&self, haystack: &[u8]) -> Option<usize> {
+        let needle = &self.needle;
+        let n = needle.len();
+        match n {
+            0 => return Some(0),
+            1 => return memchr(needle[0], haystack),
+            _ if n > haystack.len() => return None,
+            _ => {}
+        }
+        // One past the last position the needle could start at.
+        let last = haystack.len() - n + 1;
+        let (critical, rare) = (self.critical, self.rare);
+        let mut pos = 0;
+        // How much of the needle's start is known to match at `pos`.
+        let mut memory = 0;
+
+        while pos < last {
+            if memory == 0 {
+                // Nothing can match until the rare byte lines up.
+                let window = &haystack[pos + rare..last + rare];
+                pos += memchr(needle[rare], window)?;
+            }
+
+            // Right half first, left to right...
+            let start = if self.periodic {
+                critical.max(memory)
+            } else {
+                critical
+            };
+            let mismatch = (start..n).find(|&i| needle[i] != haystack[pos + i]);
+            if let Some(i) = mismatch {
+                pos += i - critical + 1;
+                memory = 0;
+                continue;
+            }
+
+            // ...then the left half, right to left.
+            let low = if self.periodic { memory } else { 0 };
+            if (low..critical)
+                .rev()
+                .all(|i| needle[i] == haystack[pos + i])
+            {
+                return Some(pos);
+            }
+            pos += self.period;
+            if self.periodic {
+                memory = n - self.period;
+            }
+        }
+        None
+    }
+
+    /// The lines of `haystack` that contain the needle, as byte ranges that
+    /// leave out the '\n'. The needle must not contain a '\n' itself.
+    pub fn find_lines<'a>(&'a self, haystack: &'a [u8]) -> FindLines<'a> {
+        FindLines {
+            finder: self,
+            haystack,
+            pos: 0,
+        }
+    }
+}
+
+/// Iterator returned by `Finder::find_lines`.
+pub struct FindLines<'a> {
+    finder: &'a Finder,
+    haystack: &'a [u8],
+    // Start of the next line to search.
+    pos: usize,
+}
+
+impl Iterator for FindLines<'_> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        let haystack = self.haystack;
+        let pos = self.pos;
+        if pos > haystack.len() {
+            return None;
+        }
+        let hit = pos + self.finder.find(&haystack[pos..])?;
+        // Line boundaries are only looked for around the hit.
+        let start = memrchr(b'\n', &haystack[pos..hit]).map_or(pos, |i| pos + i + 1);
+        let end = memchr(b'\n', &haystack[hit..]).map_or(haystack.len(), |i| hit + i);
+        self.pos = end + 1;
+        Some((start, end))
+    }
+}
+
+// Roughly how common `byte` is in text, from 0 for bytes that hardly ever
+// turn up to 9 for spaces.
+fn frequency(byte: u8) -> u8 {
+    match byte {
+        b' ' => 9,
+        b'e' | b't' | b'a' | b'o' | b'i' | b'n' => 8,
+        b's' | b'r' | b'h' | b'l' | b'd' => 7,
+        b'a'..=b'z' => 6,
+        b'\n' | b'\t' | b'.' | b',' | b'_' | b'(' | b')' | b';' | b':' => 5,
+        b'0'..=b'9' | b'=' | b'"' | b'\'' | b'-' | b'/' => 4,
+        b'A'..=b'Z' | b'!'..=b'~' => 3,
+        0x80.. => 2,
+        _ => 0,
+    }
+}
+
+// Splits the needle where the local period equals the global one: the
+// later of the maximal suffixes for the two byte orders. Returns where the
+// split is and the period of the right half.
+fn critical_factorization(needle: &[u8]) -> (usize, usize) {
+    let (a, period_a) = maximal_suffix(needle, false);
+    let (b, period_b) = maximal_suffix(needle, true);
+    if a > b {
+        (a, period_a)
+    } else {
+        (b, period_b)
+    }
+}
+
+// Start and period of the lexicographically greatest suffix of `needle`
+// (with the byte order reversed if `reversed`).
+fn maximal_suffix(needle: &[u8], reversed: bool) -> (usize, usize) {
+    let mut left = 0;
+    let mut right = 1;
+    let mut offset = 0;
+    let mut period = 1;
+    while right + offset < needle.len() {
+        let a = needle[right + offset];
+        let b = needle[left + offset];
+        if (a < b && !reversed) || (a > b && reversed) {
+            // The suffix at `right` is smaller: skip past it.
+            right += offset + 1;
+            offset = 0;
+            period = right - left;
+        } else if a == b {
+            if offset + 1 == period {
+                right += offset + 1;
+                offset = 0;
+            } else {
+                offset += 1;
+            }
+        } else {
+            // The suffix at `right` is greater: it's the new candidate.
+            left = right;
+            right += 1;
+            offset = 0;
+            period = 1;
+        }
+    }
+    (left, period)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_at_every_alignment() {
+        let haystack: Vec<u8> = (0..100u8).map(|i| b'a' + i % 3).collect();
+        for len in 0..haystack.len() {
+            let slice = &haystack[..len];
+            for byte in [b'a', b'b', b'c', b'\n'] {
+                assert_eq!(slice.iter().position(|&b| b == byte), memchr(byte, slice));
+                assert_eq!(slice.iter().rposition(|&b| b == byte), memrchr(byte, slice));
+                assert_eq!(
+                    slice.iter().filter(|&&b| b == byte).count(),
+                    count(byte, slice)
+                );
+            }
+        }
+        // High bytes don't confuse the word tricks.
+        let bytes = "ÿÿÿÿÿÿÿÿÿÿ\n".as_bytes();
+        assert_eq!(Some(20), memchr(b'\n', bytes));
+        assert_eq!(1, count(b'\n', bytes));
+        assert_eq!(10, count(0xbf, bytes));
+    }
+
+    #[test]
+    fn finds_like_naive_search() {
+        let haystacks = [
+            "",
+            "a",
+            "aaaaaaaaaaaaaaaaaaaaaaaaaaaaab",
+            "abababababababababcababababababab",
+            "safe, fast, productive. Pick three. Duct tape.",
+            "abcabcabdabcabcabcabdabcabcabc abcab",
+            "naïve café, naïve caffè",
+        ];
+        let needles = [
+            "a",
+            "b",
+            "aab",
+            "aaab",
+            "ab",
+            "abab",
+            "ababc",
+            "abcabd",
+            "bca",
+            "duct",
+            "Duct",
+            "three.",
+            "zzz",
+            "café",
+            "ïve",
+            "abcabcabd",
+            "cabcab",
+        ];
+        for haystack in haystacks {
+            for needle in needles {
+                let finder = Finder::new(needle);
+                let expected: Vec<usize> = (0..haystack.len())
+                    .filter(|&i| haystack.as_bytes()[i..].starts_with(needle.as_bytes()))
+                    .collect();
+                let mut found = Vec::new();
+                let mut pos = 0;
+                while let Some(i) = finder.find(&haystack.as_bytes()[pos..]) {
+                    found.push(pos + i);
+                    pos += i + 1;
+                }
+                assert_eq!(expected, found, "{needle:?} in {haystack:?}");
+            }
+        }
+        assert_eq!(Some(0), Finder::new("").find(b"abc"));
+    }
+
+    #[test]
+    fn lines_around_hits() {
+        let finder = Finder::new("uct");
+        let text = b"Rust:\nsafe, fast, productive.\nPick three.\nDuct tape, duct tape.\nuct";
+        let lines: Vec<&[u8]> = finder
+            .find_lines(text)
+            .map(|(start, end)| &text[start..end])
+            .collect();
+        assert_eq!(
+            vec![
+                &b"safe, fast, productive."[..],
+                b"Duct tape, duct tape.",
+                b"uct"
+            ],
+            lines
+        );
+        assert_eq!(0, finder.find_lines(b"Rust:\n").count());
+    }
+}
@@ -0,0 +1,287 @@
+// A small worker pool for searching many files at once.
+//
+// Workers pull the next job index from a shared counter. The job at the
+// head of the order writes straight to the output, like a serial search
+// would; the ones after it buffer their output until it's their turn, and a
+// buffer that fills up makes its job wait for that. Workers also don't start
+// jobs too far past the head, so however big the inputs, only so much is
+// ever held in memory. The result is byte-for-byte what a serial search
+// would have printed.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Condvar, Mutex};
+use std::thread;
+
+// The most output a job that isn't at the head holds on to.
+const BUFFER_LIMIT: usize = 64 * 1024;
+// How far past the head jobs may be started, per thread.
+const JOBS_AHEAD_PER_THREAD: usize = 4;
+
+/// Runs `search` for every job on up to `threads` threads, writing each
+/// job's output to `out` and then passing its result to `finish`, in the
+/// order of `jobs`. Stops at the first error from either.
+pub fn for_each_ordered<T, R, W, F, D>(
+    jobs: &[T],
+    threads: usize,
+    out: &mut W,
+    search: F,
+    finish: D,
+) -> io::Result<()>
+where
+    T: Sync,
+    R: Send,
+    W: Write + Send,
+    F: Fn(&T, &mut JobOutput<'_, '_, W>) -> R + Sync,
+    D: FnMut(R) -> io::Result<()>,
+{
+    let threads = threads.clamp(1, jobs.len().max(1));
+    let ahead = threads * JOBS_AHEAD_PER_THREAD;
+    let next_job = AtomicUsize::new(0);
+    let state = State {
+        shared: Mutex::new(Shared {
+            out,
+            head: 0,
+            stopped: false,
+        }),
+        turn: Condvar::new(),
+    };
+    let (tx, rx) = mpsc::channel();
+
+    thread::scope(|s| {
+        for _ in 0..threads {
+            let tx = tx.clone();
+            let (next_job, state, search) = (&next_job, &state, &search);
+            s.spawn(move || loop {
+                let index = next_job.fetch_add(1, Ordering::Relaxed);
+                let Some(job) = jobs.get(index) else {
+                    break;
+                };
+                let shared = state.shared.lock().unwrap();
+                let shared = state
+                    .turn
+                    .wait_while(shared, |shared| {
+                        index >= shared.head + ahead && !shared.stopped
+                    })
+                    .unwrap();
+                if shared.stopped {
+                    break;
+                }
+                drop(shared);
+
+                let mut output = JobOutput {
+                    state,
+                    index,
+                    buf: Vec::new(),
+                };
+                let result = search(job, &mut output);
+                // The receiver is gone once the writer hit an error.
+                if tx.send((index, output.buf, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(tx);
+
+        let result = write_in_order(rx, &state, finish);
+        if result.is_err() {
+            // Don't let the workers start anything new, or wait for a turn
+            // that won't come.
+            next_job.store(jobs.len(), Ordering::Relaxed);
+            state.shared.lock().unwrap().stopped = true;
+            state.turn.notify_all();
+        }
+        result
+    })
+}
+
+struct State<'o, W> {
+    shared: Mutex<Shared<'o, W>>,
+    // Signalled whenever the head moves on.
+    turn: Condvar,
+}
+
+struct Shared<'o, W> {
+    out: &'o mut W,
+    // The first job whose results haven't been finished.
+    head: usize,
+    // Set when the run ended early.
+    stopped: bool,
+}
+
+/// Where a job writes its output: straight out once it's at the head, and
+/// into a buffer until then.
+pub struct JobOutput<'s, 'o, W> {
+    state: &'s State<'o, W>,
+    index: usize,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> Write for JobOutput<'_, '_, W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let mut shared = self.state.shared.lock().unwrap();
+        if shared.head != self.index {
+            if self.buf.len() + data.len() <= BUFFER_LIMIT {
+                self.buf.extend_from_slice(data);
+                return Ok(data.len());
+            }
+            shared = self
+                .state
+                .turn
+                .wait_while(shared, |shared| {
+                    shared.head != self.index && !shared.stopped
+                })
+                .unwrap();
+            if shared.stopped {
+                return Err(io::Error::other("the search was stopped"));
+            }
+        }
+        // What was held back goes first.
+        if !self.buf.is_empty() {
+            shared.out.write_all(&self.buf)?;
+            self.buf.clear();
+        }
+        shared.out.write_all(data)?;
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut shared = self.state.shared.lock().unwrap();
+        if shared.head == self.index && self.buf.is_empty() {
+            shared.out.flush()?;
+        }
+        Ok(())
+    }
+}
+
+fn write_in_order<R, W: Write>(
+    rx: mpsc::Receiver<(usize, Vec<u8>, R)>,
+    state: &State<'_, W>,
+    mut finish: impl FnMut(R) -> io::Result<()>,
+) -> io::Result<()> {
+    // Results that finished early wait here until everything before them
+    // has been written. There are never more than the jobs that may be
+    // started past the head.
+    let mut pending = HashMap::new();
+    let mut next = 0;
+    for (index, buf, result) in rx {
+        pending.insert(index, (buf, result));
+        while let Some((buf, result)) = pending.remove(&next) {
+            state.shared.lock().unwrap().out.write_all(&buf)?;
+            finish(result)?;
+            next += 1;
+            state.shared.lock().unwrap().head = next;
+            state.turn.notify_all();
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn output_follows_job_order() {
+        let jobs: Vec<u64> = (0..16).collect();
+        let mut out = Vec::new();
+        // Later jobs finish first.
+        for_each_ordered(
+            &jobs,
+            4,
+            &mut out,
+            |&job, buf| {
+                thread::sleep(Duration::from_millis(16 - job));
+                writeln!(buf, "job {job}")
+            },
+            |result| result,
+        )
+        .unwrap();
+
+        let expected: String = jobs.iter().map(|job| format!("job {job}\n")).collect();
+        assert_eq!(expected, String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn stops_at_first_error() {
+        let jobs: Vec<u64> = (0..8).collect();
+        let mut out = Vec::new();
+        let result = for_each_ordered(
+            &jobs,
+            3,
+            &mut out,
+            |&job, buf| {
+                if job == 5 {
+                    return Err(io::Error::new(io::ErrorKind::NotFound, "job 5"));
+                }
+                writeln!(buf, "job {job}")
+            },
+            |result| result,
+        );
+
+        assert_eq!("job 5", result.unwrap_err().to_string());
+        assert_eq!(
+            "job 0\njob 1\njob 2\njob 3\njob 4\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    // Sends everything written to it down a channel.
+    struct Tap(mpsc::Sender<Vec<u8>>);
+
+    impl Write for Tap {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            self.0.send(data.to_vec()).unwrap();
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn head_streams_and_the_rest_wait() {
+        let (tx, rx) = mpsc::channel();
+        let written = Mutex::new(rx);
+        let buffered = AtomicUsize::new(0);
+        let jobs = [0, 1];
+        let mut out = Tap(tx);
+
+        for_each_ordered(
+            &jobs,
+            2,
+            &mut out,
+            |&job, buf| {
+                if job == 0 {
+                    // The first job's output is out before it's done...
+                    buf.write_all(b"first\n").unwrap();
+                    let written = written.lock().unwrap();
+                    let first = written.recv_timeout(Duration::from_secs(5)).unwrap();
+                    assert_eq!(b"first\n", &first[..]);
+                    // ...while the second job can only get so far ahead.
+                    thread::sleep(Duration::from_millis(100));
+                    assert!(buffered.load(Ordering::SeqCst) <= BUFFER_LIMIT);
+                } else {
+                    for _ in 0..4 * BUFFER_LIMIT / 1024 {
+                        buf.write_all(&[b'x'; 1024]).unwrap();
+                        buffered.fetch_add(1024, Ordering::SeqCst);
+                    }
+                }
+            },
+            |()| Ok(()),
+        )
+        .unwrap();
+        drop(out);
+
+        let rest: usize = written
+            .into_inner()
+            .unwrap()
+            .iter()
+            .map(|data| data.len())
+            .sum();
+        assert_eq!(4 * BUFFER_LIMIT, rest);
+    }
+}
@@ -0,0 +1,538 @@
+// Formatting of output lines: plain grep-style text, optionally colored with
+// ANSI escapes, or JSON Lines for editor integrations. `StandardSink` is how
+// the CLI feeds a `Searcher`'s results through it.
+
+use std::io::{self, IsTerminal, Write};
+use std::time::Duration;
+
+use crate::searcher::{FileSummary, Sink, SinkContext, SinkMatch, Stats};
+use crate::{ColorChoice, Config, LineMatch};
+
+// The same defaults as GNU grep's GREP_COLORS.
+const MATCH_COLOR: &str = "\x1b[01;31m";
+const PATH_COLOR: &str = "\x1b[35m";
+const LINE_NUMBER_COLOR: &str = "\x1b[32m";
+const SEPARATOR_COLOR: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+pub struct Printer {
+    color: bool,
+    json: bool,
+    line_number: bool,
+    // -c, --count-matches and -l replace the lines with one summary per
+    // input.
+    count: bool,
+    count_matches: bool,
+    files_with_matches: bool,
+}
+
+impl Printer {
+    pub fn new(config: &Config) -> Printer {
+        let color = match config.color {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => io::stdout().is_terminal(),
+        };
+        Printer {
+            color: color && !config.json,
+            json: config.json,
+            line_number: config.line_number,
+            count: config.count,
+            count_matches: config.count_matches,
+            files_with_matches: config.files_with_matches,
+        }
+    }
+
+    /// A sink that prints one input's results to `out`, prefixing each line
+    /// with the input's name when `with_path` is set.
+    pub fn sink<W: Write>(&self, out: W, with_path: bool) -> StandardSink<'_, W> {
+        StandardSink {
+            printer: self,
+            out,
+            with_path,
+            name: String::new(),
+        }
+    }
+
+    /// Whether `line` wants the byte ranges of the matches in each line.
+    pub fn needs_matches(&self) -> bool {
+        self.color || self.json
+    }
+
+    /// The `--` between groups of context that aren't adjacent.
+    pub fn context_break(&self, out: &mut impl Write) -> io::Result<()> {
+        if self.json {
+            return Ok(());
+        }
+        self.paint(out, SEPARATOR_COLOR, "--")?;
+        writeln!(out)
+    }
+
+    pub fn line(
+        &self,
+        out: &mut impl Write,
+        name: &str,
+        with_path: bool,
+        record: &LineMatch,
+        matches: &[(usize, usize)],
+        distance: Option<usize>,
+    ) -> io::Result<()> {
+        if self.json {
+            return self.json_line(out, name, record, matches, distance);
+        }
+
+        // Like grep, context lines use '-' where selected lines use ':'.
+        let separator = if record.is_context { "-" } else { ":" };
+        // A --multiline match can cover several lines, and each of them is
+        // printed as usual, with its own number.
+        let text = record.line;
+        let mut line_number = record.line_number;
+        let mut line_start = 0;
+        loop {
+            let line_end = text[line_start..]
+                .find('\n')
+                .map_or(text.len(), |i| line_start + i);
+            let last = line_end == text.len();
+            let line = &text[line_start..line_end];
+            let line = if last {
+                line
+            } else {
+                line.strip_suffix('\r').unwrap_or(line)
+            };
+
+            if with_path {
+                self.paint(out, PATH_COLOR, name)?;
+                self.paint(out, SEPARATOR_COLOR, separator)?;
+            }
+            if self.line_number {
+                self.paint(out, LINE_NUMBER_COLOR, &line_number.to_string())?;
+                self.paint(out, SEPARATOR_COLOR, separator)?;
+            }
+            // --fuzzy hits show how many edits they're off by.
+            if let Some(distance) = distance {
+                self.paint(out, LINE_NUMBER_COLOR, &format!("~{distance}"))?;
+                self.paint(out, SEPARATOR_COLOR, separator)?;
+            }
+
+            // The parts of the matches that fall on this line.
+            let mut written = 0;
+            for &(start, end) in matches {
+                let start = start.saturating_sub(line_start).min(line.len());
+                let end = end.saturating_sub(line_start).min(line.len());
+                if start < end {
+                    out.write_all(&line.as_bytes()[written..start])?;
+                    self.paint(out, MATCH_COLOR, &line[start..end])?;
+                    written = end;
+                }
+            }
+            writeln!(out, "{}", &line[written..])?;
+
+            if last {
+                return Ok(());
+            }
+            line_start = line_end + 1;
+            line_number += 1;
+        }
+    }
+
+    /// The --stats report: `stats` for every input, and `total`, how long
+    /// the whole run took.
+    pub fn stats(&self, out: &mut impl Write, stats: &Stats, total: Duration) -> io::Result<()> {
+        if self.json {
+            return writeln!(
+                out,
+                "{{\"type\":\"stats\",\"matches\":{},\"matched_lines\":{},\
+                 \"files_matched\":{},\"files_searched\":{},\"bytes_searched\":{},\
+                 \"seconds_searching\":{:.6},\"seconds_total\":{:.6}}}",
+                stats.matches,
+                stats.matched_lines,
+                stats.files_matched,
+                stats.files_searched,
+                stats.bytes_searched,
+                stats.elapsed.as_secs_f64(),
+                total.as_secs_f64()
+            );
+        }
+        writeln!(out)?;
+        writeln!(out, "{} matches", stats.matches)?;
+        writeln!(out, "{} matched lines", stats.matched_lines)?;
+        writeln!(out, "{} files contained matches", stats.files_matched)?;
+        writeln!(out, "{} files searched", stats.files_searched)?;
+        writeln!(out, "{} bytes searched", stats.bytes_searched)?;
+        writeln!(
+            out,
+            "{:.6} seconds spent searching",
+            stats.elapsed.as_secs_f64()
+        )?;
+        writeln!(out, "{:.6} seconds total", total.as_secs_f64())
+    }
+
+    fn paint(&self, out: &mut impl Write, color: &str, text: &str) -> io::Result<()> {
+        if self.color {
+            write!(out, "{color}{text}{RESET}")
+        } else {
+            out.write_all(text.as_bytes())
+        }
+    }
+
+    fn json_line(
+        &self,
+        out: &mut impl Write,
+        name: &str,
+        record: &LineMatch,
+        matches: &[(usize, usize)],
+        distance: Option<usize>,
+    ) -> io::Result<()> {
+        let kind = if record.is_context {
+            "context"
+        } else {
+            "match"
+        };
+        write!(out, "{{\"type\":\"{kind}\",\"path\":")?;
+        write_json_string(out, name)?;
+        write!(out, ",\"line_number\":{}", record.line_number)?;
+        // Where a --multiline match ends.
+        if record.end_line_number > record.line_number {
+            write!(out, ",\"end_line_number\":{}", record.end_line_number)?;
+        }
+        if let Some(distance) = distance {
+            write!(out, ",\"distance\":{distance}")?;
+        }
+        write!(out, ",\"text\":")?;
+        write_json_string(out, record.line)?;
+        write!(out, ",\"matches\":[")?;
+        for (i, &(start, end)) in matches.iter().enumerate() {
+            if i > 0 {
+                write!(out, ",")?;
+            }
+            write!(out, "{{\"start\":{start},\"end\":{end},\"text\":")?;
+            write_json_string(out, &record.line[start..end])?;
+            write!(out, "}}")?;
+        }
+        writeln!(out, "]}}")
+    }
+}
+
+pub struct StandardSink<'p, W> {
+    printer: &'p Printer,
+    out: W,
+    with_path: bool,
+    name: String,
+}
+
+impl<W: Write> Sink for StandardSink<'_, W> {
+    fn begin(&mut self, name: &str) -> io::Result<()> {
+        self.name.clear();
+        self.name.push_str(name);
+        Ok(())
+    }
+
+    fn matched(&mut self, line: &SinkMatch<'_>) -> io::Result<bool> {
+        if self.printer.files_with_matches {
+            // One hit is enough to name the file; stop reading.
+            return Ok(false);
+        }
+        if self.printer.count || self.printer.count_matches {
+            return Ok(true);
+        }
+        // With --replace the new text is what gets highlighted.
+        let replaced = line.replaced();
+        let (text, matches) = match &replaced {
+            Some((text, ranges)) => (text.as_str(), ranges.clone()),
+            None if self.printer.needs_matches() => (line.line, line.matches()),
+            None => (line.line, Vec::new()),
+        };
+        let record = LineMatch {
+            line_number: line.line_number,
+            end_line_number: line.end_line_number,
+            line: text,
+            is_context: false,
+        };
+        self.printer.line(
+            &mut self.out,
+            &self.name,
+            self.with_path,
+            &record,
+            &matches,
+            line.distance,
+        )?;
+        Ok(true)
+    }
+
+    fn context(&mut self, line: &SinkContext<'_>) -> io::Result<bool> {
+        let record = LineMatch {
+            line_number: line.line_number,
+            end_line_number: line.line_number,
+            line: line.line,
+            is_context: true,
+        };
+        self.printer.line(
+            &mut self.out,
+            &self.name,
+            self.with_path,
+            &record,
+            &[],
+            None,
+        )?;
+        Ok(true)
+    }
+
+    fn context_break(&mut self) -> io::Result<()> {
+        self.printer.context_break(&mut self.out)
+    }
+
+    fn end(&mut self, name: &str, summary: &FileSummary) -> io::Result<()> {
+        let count = if self.printer.count_matches {
+            summary.matches
+        } else {
+            summary.matched_lines
+        };
+        if self.printer.json {
+            return self.json_end(name, summary);
+        }
+        if self.printer.files_with_matches {
+            if summary.matched_lines > 0 {
+                writeln!(self.out, "{name}")?;
+            }
+        } else if self.printer.count || self.printer.count_matches {
+            if self.with_path {
+                writeln!(self.out, "{name}:{count}")?;
+            } else {
+                writeln!(self.out, "{count}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> StandardSink<'_, W> {
+    // The JSON form of `end`: a "summary" record naming each file with a
+    // selected line for -l, or a "count" record for every input for -c and
+    // --count-matches. Input names are always included.
+    fn json_end(&mut self, name: &str, summary: &FileSummary) -> io::Result<()> {
+        let out = &mut self.out;
+        if self.printer.files_with_matches {
+            if summary.matched_lines > 0 {
+                write!(out, "{{\"type\":\"summary\",\"path\":")?;
+                write_json_string(out, name)?;
+                writeln!(out, "}}")?;
+            }
+        } else if self.printer.count || self.printer.count_matches {
+            let (field, count) = if self.printer.count_matches {
+                ("matches", summary.matches)
+            } else {
+                ("matched_lines", summary.matched_lines)
+            };
+            write!(out, "{{\"type\":\"count\",\"path\":")?;
+            write_json_string(out, name)?;
+            writeln!(out, ",\"{field}\":{count}}}")?;
+        }
+        Ok(())
+    }
+}
+
+fn write_json_string(out: &mut impl Write, s: &str) -> io::Result<()> {
+    write!(out, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(out, "\\\"")?,
+            '\\' => write!(out, "\\\\")?,
+            '\n' => write!(out, "\\n")?,
+            '\r' => write!(out, "\\r")?,
+            '\t' => write!(out, "\\t")?,
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32)?,
+            c => write!(out, "{c}")?,
+        }
+    }
+    write!(out, "\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn printer(color: bool, json: bool) -> Printer {
+        Printer {
+            color,
+            json,
+            line_number: true,
+            count: false,
+            count_matches: false,
+            files_with_matches: false,
+        }
+    }
+
+    fn render(
+        printer: &Printer,
+        record: &LineMatch,
+        matches: &[(usize, usize)],
+        distance: Option<usize>,
+    ) -> String {
+        let mut out = Vec::new();
+        printer
+            .line(&mut out, "poem.txt", true, record, matches, distance)
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn highlights_matches() {
+        let record = LineMatch {
+            line_number: 2,
+            end_line_number: 2,
+            line: "safe, fast, productive.",
+            is_context: false,
+        };
+        assert_eq!(
+            "poem.txt:2:safe, fast, productive.\n",
+            render(&printer(false, false), &record, &[(15, 19)], None)
+        );
+        assert_eq!(
+            "\x1b[35mpoem.txt\x1b[0m\x1b[36m:\x1b[0m\x1b[32m2\x1b[0m\x1b[36m:\x1b[0m\
+             safe, fast, pro\x1b[01;31mduct\x1b[0mive.\n",
+            render(&printer(true, false), &record, &[(15, 19)], None)
+        );
+    }
+
+    #[test]
+    fn json_lines() {
+        let record = LineMatch {
+            line_number: 4,
+            end_line_number: 4,
+            line: "\"Duct\"\ttape.",
+            is_context: false,
+        };
+        assert_eq!(
+            "{\"type\":\"match\",\"path\":\"poem.txt\",\"line_number\":4,\
+             \"text\":\"\\\"Duct\\\"\\ttape.\",\
+             \"matches\":[{\"start\":1,\"end\":5,\"text\":\"Duct\"}]}\n",
+            render(&printer(false, true), &record, &[(1, 5)], None)
+        );
+    }
+
+    #[test]
+    fn fuzzy_distances() {
+        let record = LineMatch {
+            line_number: 3,
+            end_line_number: 3,
+            line: "failed to recieve",
+            is_context: false,
+        };
+        assert_eq!(
+            "poem.txt:3:~2:failed to recieve\n",
+            render(&printer(false, false), &record, &[], Some(2))
+        );
+        assert_eq!(
+            "{\"type\":\"match\",\"path\":\"poem.txt\",\"line_number\":3,\"distance\":2,\
+             \"text\":\"failed to recieve\",\"matches\":[]}\n",
+            render(&printer(false, true), &record, &[], Some(2))
+        );
+    }
+
+    #[test]
+    fn json_summaries() {
+        let summary = FileSummary {
+            matched_lines: 2,
+            matches: 3,
+            ..FileSummary::default()
+        };
+        let end = |printer: &Printer, summary: &FileSummary| {
+            let mut out = Vec::new();
+            printer
+                .sink(&mut out, false)
+                .end("poem.txt", summary)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let mut json = printer(false, true);
+        json.count = true;
+        assert_eq!(
+            "{\"type\":\"count\",\"path\":\"poem.txt\",\"matched_lines\":2}\n",
+            end(&json, &summary)
+        );
+        json.count_matches = true;
+        assert_eq!(
+            "{\"type\":\"count\",\"path\":\"poem.txt\",\"matches\":3}\n",
+            end(&json, &summary)
+        );
+
+        let mut json = printer(false, true);
+        json.files_with_matches = true;
+        assert_eq!(
+            "{\"type\":\"summary\",\"path\":\"poem.txt\"}\n",
+            end(&json, &summary)
+        );
+        assert_eq!("", end(&json, &FileSummary::default()));
+    }
+
+    #[test]
+    fn stats_report() {
+        let mut stats = Stats::default();
+        stats.add(&FileSummary {
+            matched_lines: 2,
+            matches: 3,
+            bytes_searched: 1_000,
+            elapsed: Duration::from_micros(1_500),
+        });
+        stats.add(&FileSummary {
+            bytes_searched: 24,
+            elapsed: Duration::from_micros(500),
+            ..FileSummary::default()
+        });
+
+        let mut out = Vec::new();
+        printer(false, false)
+            .stats(&mut out, &stats, Duration::from_millis(3))
+            .unwrap();
+        assert_eq!(
+            "\n3 matches\n2 matched lines\n1 files contained matches\n2 files searched\n\
+             1024 bytes searched\n0.002000 seconds spent searching\n0.003000 seconds total\n",
+            String::from_utf8(out).unwrap()
+        );
+
+        let mut out = Vec::new();
+        printer(false, true)
+            .stats(&mut out, &stats, Duration::from_millis(3))
+            .unwrap();
+        assert_eq!(
+            "{\"type\":\"stats\",\"matches\":3,\"matched_lines\":2,\"files_matched\":1,\
+             \"files_searched\":2,\"bytes_searched\":1024,\"seconds_searching\":0.002000,\
+             \"seconds_total\":0.003000}\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn multiline_blocks() {
+        let record = LineMatch {
+            line_number: 2,
+            end_line_number: 3,
+            line: "safe, fast, productive.\r\nPick three.",
+            is_context: false,
+        };
+        assert_eq!(
+            "poem.txt:2:safe, fast, productive.\npoem.txt:3:Pick three.\n",
+            render(&printer(false, false), &record, &[(12, 29)], None)
+        );
+        // A match over the line break is highlighted on both lines.
+        let prefix = |n: usize| {
+            format!("\x1b[35mpoem.txt\x1b[0m\x1b[36m:\x1b[0m\x1b[32m{n}\x1b[0m\x1b[36m:\x1b[0m")
+        };
+        assert_eq!(
+            format!(
+                "{}safe, fast, \x1b[01;31mproductive.\x1b[0m\n\
+                 {}\x1b[01;31mPick\x1b[0m three.\n",
+                prefix(2),
+                prefix(3)
+            ),
+            render(&printer(true, false), &record, &[(12, 29)], None)
+        );
+        assert_eq!(
+            "{\"type\":\"match\",\"path\":\"poem.txt\",\"line_number\":2,\"end_line_number\":3,\
+             \"text\":\"safe, fast, productive.\\r\\nPick three.\",\
+             \"matches\":[{\"start\":12,\"end\":29,\"text\":\"productive.\\r\\nPick\"}]}\n",
+            render(&printer(false, true), &record, &[(12, 29)], None)
+        );
+    }
+}
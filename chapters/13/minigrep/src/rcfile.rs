@@ -0,0 +1,200 @@
+// The config file: `$MINIGREP_CONFIG`, or else `~/.minigreprc`.
+//
+//     # Default flags first, one per line as on the command line.
+//     --smart-case
+//     --context=2
+//
+//     [types]
+//     rust = *.rs
+//     web = *.html, *.css, *.js
+//
+//     [ignore]
+//     target/
+//     *.min.js
+//
+// `[types]` names sets of file name globs for `--type`, and `[ignore]` holds
+// gitignore-style rules for files to skip when walking directories. The
+// flags act as if they came first on the command line, so the command line
+// overrides any of them that take a value. Pointing `MINIGREP_CONFIG` at
+// different files gives different profiles; setting it empty, or passing
+// `--no-config`, turns the file off.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::glob::Glob;
+use crate::walk::Filter;
+use crate::ConfigError;
+
+const DEFAULT_NAME: &str = ".minigreprc";
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RcFile {
+    pub path: String,
+    pub flags: Vec<String>,
+    pub types: Vec<(String, Vec<String>)>,
+    pub ignore: Vec<String>,
+}
+
+#[derive(Clone, Copy)]
+enum Section {
+    Flags,
+    Types,
+    Ignore,
+}
+
+impl RcFile {
+    /// Reads the config file, if there is one.
+    pub fn load() -> Result<Option<RcFile>, ConfigError> {
+        let path = match env::var_os("MINIGREP_CONFIG") {
+            Some(path) if path.is_empty() => return Ok(None),
+            Some(path) => PathBuf::from(path),
+            None => {
+                let Some(home) = env::var_os("HOME") else {
+                    return Ok(None);
+                };
+                let path = Path::new(&home).join(DEFAULT_NAME);
+                if !path.is_file() {
+                    return Ok(None);
+                }
+                path
+            }
+        };
+        let name = path.display().to_string();
+        let contents = fs::read_to_string(&path).map_err(|err| ConfigError::RcFile {
+            path: name.clone(),
+            reason: err.to_string(),
+        })?;
+        RcFile::parse(&name, &contents).map(Some)
+    }
+
+    pub fn parse(path: &str, contents: &str) -> Result<RcFile, ConfigError> {
+        let mut rc = RcFile {
+            path: path.to_string(),
+            ..RcFile::default()
+        };
+        let mut section = Section::Flags;
+
+        for (i, line) in contents.lines().enumerate() {
+            let error = |reason: String| ConfigError::RcFile {
+                path: path.to_string(),
+                reason: format!("line {}: {reason}", i + 1),
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = match name.trim() {
+                    "types" => Section::Types,
+                    "ignore" => Section::Ignore,
+                    _ => return Err(error(format!("unknown section '[{name}]'"))),
+                };
+                continue;
+            }
+
+            match section {
+                Section::Flags => {
+                    if !line.starts_with('-') || line == "-" || line == "--" {
+                        return Err(error(format!("expected an option, found '{line}'")));
+                    }
+                    rc.flags.push(line.to_string());
+                }
+                Section::Types => {
+                    let (name, globs) = line
+                        .split_once('=')
+                        .ok_or_else(|| error("expected 'NAME = GLOB, ...'".to_string()))?;
+                    let name = name.trim();
+                    if name.is_empty() {
+                        return Err(error("missing type name".to_string()));
+                    }
+                    let globs: Vec<String> = globs
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|glob| !glob.is_empty())
+                        .map(String::from)
+                        .collect();
+                    for glob in &globs {
+                        Glob::new(glob).map_err(|err| error(err.to_string()))?;
+                    }
+                    // A later definition replaces an earlier one.
+                    rc.types.retain(|(other, _)| other != name);
+                    rc.types.push((name.to_string(), globs));
+                }
+                Section::Ignore => {
+                    Filter::default()
+                        .ignore(line)
+                        .map_err(|err| error(err.to_string()))?;
+                    rc.ignore.push(line.to_string());
+                }
+            }
+        }
+        Ok(rc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sections() {
+        let rc = RcFile::parse(
+            "minigreprc",
+            "\
+# defaults
+--smart-case
+  -n
+
+[types]
+rust = *.rs
+web = *.html, *.css,*.js
+rust = *.rs, Cargo.toml
+
+[ignore]
+target/
+!keep.min.js
+",
+        )
+        .unwrap();
+        assert_eq!(vec!["--smart-case", "-n"], rc.flags);
+        assert_eq!(
+            vec![
+                ("web".to_string(), vec!["*.html", "*.css", "*.js"]),
+                ("rust".to_string(), vec!["*.rs", "Cargo.toml"]),
+            ],
+            rc.types
+                .iter()
+                .map(|(name, globs)| (name.clone(), globs.iter().map(String::as_str).collect()))
+                .collect::<Vec<(String, Vec<&str>)>>()
+        );
+        assert_eq!(vec!["target/", "!keep.min.js"], rc.ignore);
+    }
+
+    #[test]
+    fn errors() {
+        for (contents, reason) in [
+            ("-n\nduct\n", "line 2: expected an option, found 'duct'"),
+            ("[colors]\n", "line 1: unknown section '[colors]'"),
+            (
+                "[types]\nrust *.rs\n",
+                "line 2: expected 'NAME = GLOB, ...'",
+            ),
+            ("[types]\n = *.rs\n", "line 2: missing type name"),
+            (
+                "[ignore]\n\n[a-\n",
+                "line 3: invalid glob '[a-': unclosed character class",
+            ),
+        ] {
+            assert_eq!(
+                Err(ConfigError::RcFile {
+                    path: "rc".to_string(),
+                    reason: reason.to_string()
+                }),
+                RcFile::parse("rc", contents),
+                "{contents:?}"
+            );
+        }
+    }
+}
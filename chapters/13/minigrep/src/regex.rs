@@ -0,0 +1,985 @@
+// A small regular expression engine for minigrep's `-E` mode.
+//
+// Patterns are parsed into a syntax tree, compiled into a list of
+// instructions, and executed with a Pike VM: every possible NFA thread is
+// advanced in lockstep over the input, so matching time stays linear in the
+// length of the haystack no matter how the pattern is written (no
+// catastrophic backtracking). Threads are kept in priority order, which gives
+// the usual leftmost-first ("Perl-like") semantics for alternation and greedy
+// or lazy repetition.
+//
+// Supported syntax:
+//   literals, `.`, `^`, `$`, `\b`, `\B`
+//   (`^` and `$` match at the start and end of the text, or of every line
+//   when built with `build_multi_line`)
+//   classes `[abc]`, `[a-z]`, `[^...]`, and `\d \w \s` (plus negations)
+//   groups `(...)`, named groups `(?P<name>...)` or `(?<name>...)`,
+//   non-capturing groups `(?:...)`, alternation `|`
+//   repetition `*`, `+`, `?`, `{n}`, `{n,}`, `{n,m}`, lazy with a trailing `?`
+
+use std::error::Error;
+use std::fmt;
+
+use crate::fold::fold_char;
+
+// Upper bound for `{n,m}` counts, so that `a{100000}` can't blow up memory.
+const MAX_REPEAT: u32 = 1000;
+const MAX_INSTS: usize = 100_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegexError {
+    msg: String,
+}
+
+impl RegexError {
+    pub(crate) fn new(msg: impl Into<String>) -> RegexError {
+        RegexError { msg: msg.into() }
+    }
+}
+
+impl fmt::Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid regex: {}", self.msg)
+    }
+}
+
+impl Error for RegexError {}
+
+#[derive(Debug, Clone)]
+pub struct Regex {
+    insts: Vec<Inst>,
+    // Two slots (start, end) per group; group 0 is the whole match.
+    slots: usize,
+    // Named groups and their indexes.
+    names: Vec<(String, usize)>,
+    anchored: bool,
+    ignore_case: bool,
+}
+
+/// Where each group of a match landed. Group 0 is the whole match; groups
+/// that took no part in it have no range.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Captures {
+    slots: Vec<Option<usize>>,
+}
+
+impl Captures {
+    // Just group 0, for matchers that have no groups.
+    pub(crate) fn whole(start: usize, end: usize) -> Captures {
+        Captures {
+            slots: vec![Some(start), Some(end)],
+        }
+    }
+
+    pub fn get(&self, group: usize) -> Option<(usize, usize)> {
+        let start = (*self.slots.get(group * 2)?)?;
+        let end = (*self.slots.get(group * 2 + 1)?)?;
+        Some((start, end))
+    }
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, RegexError> {
+        Regex::build(pattern, false)
+    }
+
+    pub fn build(pattern: &str, ignore_case: bool) -> Result<Regex, RegexError> {
+        Regex::compile(pattern, ignore_case, false, false)
+    }
+
+    /// Like `build`, for searching text that spans several lines: `^` and
+    /// `$` match at the start and end of every line ("\n" or "\r\n").
+    pub fn build_multi_line(pattern: &str, ignore_case: bool) -> Result<Regex, RegexError> {
+        Regex::compile(pattern, ignore_case, true, false)
+    }
+
+    /// Like `build`, or `build_multi_line` with `multi_line`, but only finds
+    /// matches that don't touch a word character on either side (grep's
+    /// -w). Every way the pattern can match at a position is tried, so with
+    /// `ab|abc` the "abc" in "abc" is found.
+    pub fn build_words(
+        pattern: &str,
+        ignore_case: bool,
+        multi_line: bool,
+    ) -> Result<Regex, RegexError> {
+        Regex::compile(pattern, ignore_case, multi_line, true)
+    }
+
+    fn compile(
+        pattern: &str,
+        ignore_case: bool,
+        multi_line: bool,
+        words: bool,
+    ) -> Result<Regex, RegexError> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+            groups: 0,
+            names: Vec::new(),
+            multi_line,
+        };
+        let mut ast = parser.parse()?;
+        if words {
+            ast = Node::Concat(vec![
+                Node::Look(Look::NoWordBefore),
+                ast,
+                Node::Look(Look::NoWordAfter),
+            ]);
+        }
+        let anchored = ast.is_anchored_start();
+
+        let mut compiler = Compiler { insts: Vec::new() };
+        compiler.push(Inst::Save(0))?;
+        compiler.compile(&ast)?;
+        compiler.push(Inst::Save(1))?;
+        compiler.push(Inst::Match)?;
+
+        Ok(Regex {
+            insts: compiler.insts,
+            slots: (parser.groups + 1) * 2,
+            names: parser.names,
+            anchored,
+            ignore_case,
+        })
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.find(text).is_some()
+    }
+
+    /// Byte range of the leftmost match in `text`.
+    pub fn find(&self, text: &str) -> Option<(usize, usize)> {
+        self.find_at(text, 0)
+    }
+
+    /// Like `find`, but starts looking at byte offset `start`. Anchors and
+    /// word boundaries still see the text before `start`.
+    pub fn find_at(&self, text: &str, start: usize) -> Option<(usize, usize)> {
+        let slots = self.exec(text, start)?;
+        Some((slots[0]?, slots[1]?))
+    }
+
+    /// Like `find_at`, but with the range of every group.
+    pub fn captures_at(&self, text: &str, start: usize) -> Option<Captures> {
+        let slots = self.exec(text, start)?;
+        Some(Captures { slots })
+    }
+
+    /// Number of capture groups, not counting group 0.
+    pub fn group_count(&self) -> usize {
+        self.slots / 2 - 1
+    }
+
+    /// Index of the group called `name`.
+    pub fn group_index(&self, name: &str) -> Option<usize> {
+        self.names
+            .iter()
+            .find(|(group, _)| group == name)
+            .map(|&(_, index)| index)
+    }
+
+    fn exec(&self, text: &str, start: usize) -> Option<Vec<Option<usize>>> {
+        let mut clist = Threads::new(self.insts.len(), self.slots);
+        let mut nlist = Threads::new(self.insts.len(), self.slots);
+        let mut stack = Vec::new();
+        let mut caps = vec![None; self.slots];
+        let mut matched = None;
+        let mut pos = start;
+
+        loop {
+            // Start a new (lowest priority) thread here unless a match has
+            // already been found further left.
+            if matched.is_none() && (!self.anchored || pos == 0) {
+                caps.iter_mut().for_each(|slot| *slot = None);
+                self.add_thread(&mut clist, &mut stack, 0, text, pos, &mut caps);
+            }
+            if clist.is_empty() {
+                break;
+            }
+
+            let c = text[pos..].chars().next();
+            let next = pos + c.map_or(0, char::len_utf8);
+            for i in 0..clist.len() {
+                let pc = clist.dense[i];
+                let ok = match (&self.insts[pc], c) {
+                    (Inst::Match, _) => {
+                        matched = Some(clist.caps(pc).to_vec());
+                        // Everything after this thread has lower priority.
+                        break;
+                    }
+                    (Inst::Char(want), Some(c)) => self.char_eq(*want, c),
+                    (Inst::Any, Some(c)) => c != '\n',
+                    (Inst::Class(class), Some(c)) => class.matches(c, self.ignore_case),
+                    _ => false,
+                };
+                if ok {
+                    caps.copy_from_slice(clist.caps(pc));
+                    self.add_thread(&mut nlist, &mut stack, pc + 1, text, next, &mut caps);
+                }
+            }
+
+            std::mem::swap(&mut clist, &mut nlist);
+            nlist.clear();
+            if c.is_none() {
+                break;
+            }
+            pos = next;
+        }
+
+        matched
+    }
+
+    // Follows every empty transition from `pc`, adding the instructions that
+    // consume input (or report a match) to `list` in priority order.
+    fn add_thread(
+        &self,
+        list: &mut Threads,
+        stack: &mut Vec<Frame>,
+        pc: usize,
+        text: &str,
+        pos: usize,
+        caps: &mut [Option<usize>],
+    ) {
+        stack.push(Frame::Explore(pc));
+        while let Some(frame) = stack.pop() {
+            let mut pc = match frame {
+                Frame::Explore(pc) => pc,
+                Frame::Restore(slot, old) => {
+                    caps[slot] = old;
+                    continue;
+                }
+            };
+            loop {
+                if list.contains(pc) {
+                    break;
+                }
+                list.insert(pc);
+                match &self.insts[pc] {
+                    Inst::Jmp(to) => pc = *to,
+                    Inst::Split(first, second) => {
+                        stack.push(Frame::Explore(*second));
+                        pc = *first;
+                    }
+                    Inst::Save(slot) => {
+                        stack.push(Frame::Restore(*slot, caps[*slot]));
+                        caps[*slot] = Some(pos);
+                        pc += 1;
+                    }
+                    Inst::Assert(look) => {
+                        if !look.holds(text, pos) {
+                            break;
+                        }
+                        pc += 1;
+                    }
+                    Inst::Char(_) | Inst::Any | Inst::Class(_) | Inst::Match => {
+                        list.caps_mut(pc).copy_from_slice(caps);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    fn char_eq(&self, want: char, c: char) -> bool {
+        want == c || (self.ignore_case && fold_char(want) == fold_char(c))
+    }
+}
+
+pub(crate) fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+enum Frame {
+    Explore(usize),
+    Restore(usize, Option<usize>),
+}
+
+// A sparse set of instruction indexes, remembering insertion order and the
+// capture slots of the thread sitting at each instruction.
+struct Threads {
+    dense: Vec<usize>,
+    sparse: Vec<usize>,
+    slots: usize,
+    caps: Vec<Option<usize>>,
+}
+
+impl Threads {
+    fn new(len: usize, slots: usize) -> Threads {
+        Threads {
+            dense: Vec::with_capacity(len),
+            sparse: vec![0; len],
+            slots,
+            caps: vec![None; len * slots],
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.dense.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.dense.is_empty()
+    }
+
+    fn contains(&self, pc: usize) -> bool {
+        let i = self.sparse[pc];
+        i < self.dense.len() && self.dense[i] == pc
+    }
+
+    fn insert(&mut self, pc: usize) {
+        self.sparse[pc] = self.dense.len();
+        self.dense.push(pc);
+    }
+
+    fn clear(&mut self) {
+        self.dense.clear();
+    }
+
+    fn caps(&self, pc: usize) -> &[Option<usize>] {
+        &self.caps[pc * self.slots..(pc + 1) * self.slots]
+    }
+
+    fn caps_mut(&mut self, pc: usize) -> &mut [Option<usize>] {
+        &mut self.caps[pc * self.slots..(pc + 1) * self.slots]
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    Assert(Look),
+    Save(usize),
+    Split(usize, usize),
+    Jmp(usize),
+    Match,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Look {
+    StartText,
+    EndText,
+    StartLine,
+    EndLine,
+    WordBoundary,
+    NotWordBoundary,
+    // The edges of a -w match: no word character just before or after.
+    NoWordBefore,
+    NoWordAfter,
+}
+
+impl Look {
+    fn holds(self, text: &str, pos: usize) -> bool {
+        match self {
+            Look::StartText => pos == 0,
+            Look::EndText => pos == text.len(),
+            Look::StartLine => pos == 0 || text[..pos].ends_with('\n'),
+            Look::EndLine => {
+                let rest = &text[pos..];
+                rest.is_empty() || rest.starts_with('\n') || rest.starts_with("\r\n")
+            }
+            Look::WordBoundary | Look::NotWordBoundary => {
+                let before = text[..pos].chars().next_back().is_some_and(is_word_char);
+                let after = text[pos..].chars().next().is_some_and(is_word_char);
+                (before != after) == (self == Look::WordBoundary)
+            }
+            Look::NoWordBefore => !text[..pos].chars().next_back().is_some_and(is_word_char),
+            Look::NoWordAfter => !text[pos..].chars().next().is_some_and(is_word_char),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Perl {
+    Digit,
+    Word,
+    Space,
+}
+
+impl Perl {
+    fn matches(self, c: char) -> bool {
+        match self {
+            Perl::Digit => c.is_ascii_digit(),
+            Perl::Word => is_word_char(c),
+            Perl::Space => c.is_whitespace(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum ClassItem {
+    Range(char, char),
+    Perl(Perl, bool),
+}
+
+#[derive(Debug, Clone)]
+struct Class {
+    items: Vec<ClassItem>,
+    negated: bool,
+}
+
+impl Class {
+    fn perl(perl: Perl, negated: bool) -> Class {
+        Class {
+            items: vec![ClassItem::Perl(perl, negated)],
+            negated: false,
+        }
+    }
+
+    fn matches(&self, c: char, ignore_case: bool) -> bool {
+        let hit = if ignore_case {
+            self.contains(c)
+                || self.contains(fold_char(c))
+                || c.to_lowercase().any(|c| self.contains(c))
+                || c.to_uppercase().any(|c| self.contains(c))
+        } else {
+            self.contains(c)
+        };
+        hit != self.negated
+    }
+
+    fn contains(&self, c: char) -> bool {
+        self.items.iter().any(|item| match *item {
+            ClassItem::Range(lo, hi) => lo <= c && c <= hi,
+            ClassItem::Perl(perl, negated) => perl.matches(c) != negated,
+        })
+    }
+}
+
+#[derive(Debug)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    Look(Look),
+    Group(Box<Node>, Option<usize>),
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+    },
+}
+
+impl Node {
+    // True when every match has to begin at the start of the text, which
+    // lets the VM stop seeding new threads after position 0.
+    fn is_anchored_start(&self) -> bool {
+        match self {
+            Node::Look(Look::StartText) => true,
+            Node::Group(node, _) => node.is_anchored_start(),
+            Node::Concat(nodes) => nodes.first().is_some_and(Node::is_anchored_start),
+            Node::Alternate(nodes) => nodes.iter().all(Node::is_anchored_start),
+            _ => false,
+        }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    groups: usize,
+    names: Vec<(String, usize)>,
+    // Whether `^` and `$` are about lines rather than the whole text.
+    multi_line: bool,
+}
+
+impl Parser {
+    fn parse(&mut self) -> Result<Node, RegexError> {
+        let node = self.parse_alternation()?;
+        match self.peek() {
+            Some(')') => Err(RegexError::new("unmatched ')'")),
+            Some(c) => Err(RegexError::new(format!("unexpected '{c}'"))),
+            None => Ok(node),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_str(&mut self, s: &str) -> bool {
+        let len = s.chars().count();
+        let found = self.chars.len() >= self.pos + len
+            && self.chars[self.pos..self.pos + len]
+                .iter()
+                .copied()
+                .eq(s.chars());
+        if found {
+            self.pos += len;
+        }
+        found
+    }
+
+    fn parse_alternation(&mut self) -> Result<Node, RegexError> {
+        let mut branches = vec![self.parse_concat()?];
+        while self.eat('|') {
+            branches.push(self.parse_concat()?);
+        }
+        Ok(if branches.len() == 1 {
+            branches.pop().unwrap()
+        } else {
+            Node::Alternate(branches)
+        })
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, RegexError> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.parse_atom()?;
+            nodes.push(self.parse_repeat(atom)?);
+        }
+        Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.pop().unwrap(),
+            _ => Node::Concat(nodes),
+        })
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, RegexError> {
+        let c = self.next().unwrap();
+        Ok(match c {
+            '(' => {
+                let index = if self.eat_str("?:") {
+                    None
+                } else {
+                    self.groups += 1;
+                    if self.eat_str("?P<") || self.eat_str("?<") {
+                        let name = self.parse_group_name()?;
+                        self.names.push((name, self.groups));
+                    }
+                    Some(self.groups)
+                };
+                let node = self.parse_alternation()?;
+                if !self.eat(')') {
+                    return Err(RegexError::new("unclosed group"));
+                }
+                Node::Group(Box::new(node), index)
+            }
+            '[' => Node::Class(self.parse_class()?),
+            '.' => Node::Any,
+            '^' if self.multi_line => Node::Look(Look::StartLine),
+            '$' if self.multi_line => Node::Look(Look::EndLine),
+            '^' => Node::Look(Look::StartText),
+            '$' => Node::Look(Look::EndText),
+            '\\' => self.parse_escape()?,
+            '*' | '+' | '?' => {
+                return Err(RegexError::new(format!("nothing to repeat before '{c}'")))
+            }
+            c => Node::Char(c),
+        })
+    }
+
+    // The `name>` of a `(?<name>` group.
+    fn parse_group_name(&mut self) -> Result<String, RegexError> {
+        let mut name = String::new();
+        loop {
+            match self.next() {
+                Some('>') => break,
+                Some(c) if is_word_char(c) && c.is_ascii() => name.push(c),
+                _ => return Err(RegexError::new("invalid group name")),
+            }
+        }
+        if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(RegexError::new(format!("invalid group name '{name}'")));
+        }
+        if self.names.iter().any(|(other, _)| *other == name) {
+            return Err(RegexError::new(format!("duplicate group name '{name}'")));
+        }
+        Ok(name)
+    }
+
+    fn parse_escape(&mut self) -> Result<Node, RegexError> {
+        let c = self
+            .next()
+            .ok_or_else(|| RegexError::new("trailing backslash"))?;
+        Ok(match c {
+            'b' => Node::Look(Look::WordBoundary),
+            'B' => Node::Look(Look::NotWordBoundary),
+            _ => match self.escape_item(c)? {
+                ClassItem::Range(c, _) => Node::Char(c),
+                ClassItem::Perl(perl, negated) => Node::Class(Class::perl(perl, negated)),
+            },
+        })
+    }
+
+    // Escapes that mean the same thing inside and outside brackets.
+    fn escape_item(&self, c: char) -> Result<ClassItem, RegexError> {
+        Ok(match c {
+            'd' => ClassItem::Perl(Perl::Digit, false),
+            'D' => ClassItem::Perl(Perl::Digit, true),
+            'w' => ClassItem::Perl(Perl::Word, false),
+            'W' => ClassItem::Perl(Perl::Word, true),
+            's' => ClassItem::Perl(Perl::Space, false),
+            'S' => ClassItem::Perl(Perl::Space, true),
+            'n' => ClassItem::Range('\n', '\n'),
+            't' => ClassItem::Range('\t', '\t'),
+            'r' => ClassItem::Range('\r', '\r'),
+            c if c.is_alphanumeric() => {
+                return Err(RegexError::new(format!("unknown escape '\\{c}'")))
+            }
+            c => ClassItem::Range(c, c),
+        })
+    }
+
+    fn parse_class(&mut self) -> Result<Class, RegexError> {
+        let negated = self.eat('^');
+        let mut items = Vec::new();
+        let mut first = true;
+        loop {
+            let c = self
+                .next()
+                .ok_or_else(|| RegexError::new("unclosed character class"))?;
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+
+            let lo = if c == '\\' {
+                let e = self
+                    .next()
+                    .ok_or_else(|| RegexError::new("trailing backslash"))?;
+                match self.escape_item(e)? {
+                    ClassItem::Range(c, _) => c,
+                    perl => {
+                        items.push(perl);
+                        continue;
+                    }
+                }
+            } else {
+                c
+            };
+
+            // A '-' right before ']' is a literal dash, not a range.
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|&c| c != ']') {
+                self.pos += 1;
+                let hi = match self.next().unwrap() {
+                    '\\' => {
+                        let e = self
+                            .next()
+                            .ok_or_else(|| RegexError::new("trailing backslash"))?;
+                        match self.escape_item(e)? {
+                            ClassItem::Range(c, _) => c,
+                            _ => return Err(RegexError::new("invalid class range")),
+                        }
+                    }
+                    c => c,
+                };
+                if hi < lo {
+                    return Err(RegexError::new(format!("invalid class range {lo}-{hi}")));
+                }
+                items.push(ClassItem::Range(lo, hi));
+            } else {
+                items.push(ClassItem::Range(lo, lo));
+            }
+        }
+        Ok(Class { items, negated })
+    }
+
+    fn parse_repeat(&mut self, mut node: Node) -> Result<Node, RegexError> {
+        loop {
+            let (min, max) = match self.peek() {
+                Some('{') => match self.parse_counts()? {
+                    Some(counts) => counts,
+                    None => return Ok(node),
+                },
+                Some(op @ ('*' | '+' | '?')) => {
+                    self.pos += 1;
+                    match op {
+                        '*' => (0, None),
+                        '+' => (1, None),
+                        _ => (0, Some(1)),
+                    }
+                }
+                _ => return Ok(node),
+            };
+            let greedy = !self.eat('?');
+            node = Node::Repeat {
+                node: Box::new(node),
+                min,
+                max,
+                greedy,
+            };
+        }
+    }
+
+    // Parses `{n}`, `{n,}` or `{n,m}`. Anything else leaves the position
+    // untouched and the `{` is treated as a literal.
+    fn parse_counts(&mut self) -> Result<Option<(u32, Option<u32>)>, RegexError> {
+        let rest: String = self.chars[self.pos..].iter().collect();
+        let Some(end) = rest.find('}') else {
+            return Ok(None);
+        };
+        let body = &rest[1..end];
+        let number = |s: &str| -> Option<u32> {
+            if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            s.parse().ok()
+        };
+        let counts = match body.split_once(',') {
+            None => number(body).map(|n| (n, Some(n))),
+            Some((lo, "")) => number(lo).map(|n| (n, None)),
+            Some((lo, hi)) => number(lo).zip(number(hi)).map(|(lo, hi)| (lo, Some(hi))),
+        };
+        let Some((min, max)) = counts else {
+            return Ok(None);
+        };
+        if max.is_some_and(|max| max < min) {
+            return Err(RegexError::new(format!("invalid repetition {{{body}}}")));
+        }
+        if min > MAX_REPEAT || max.is_some_and(|max| max > MAX_REPEAT) {
+            return Err(RegexError::new(format!(
+                "repetition count exceeds {MAX_REPEAT}"
+            )));
+        }
+        self.pos += rest[..=end].chars().count();
+        Ok(Some((min, max)))
+    }
+}
+
+struct Compiler {
+    insts: Vec<Inst>,
+}
+
+impl Compiler {
+    fn push(&mut self, inst: Inst) -> Result<usize, RegexError> {
+        if self.insts.len() >= MAX_INSTS {
+            return Err(RegexError::new("pattern is too large"));
+        }
+        self.insts.push(inst);
+        Ok(self.insts.len() - 1)
+    }
+
+    fn next_pc(&self) -> usize {
+        self.insts.len()
+    }
+
+    fn compile(&mut self, node: &Node) -> Result<(), RegexError> {
+        match node {
+            Node::Empty => {}
+            Node::Char(c) => {
+                self.push(Inst::Char(*c))?;
+            }
+            Node::Any => {
+                self.push(Inst::Any)?;
+            }
+            Node::Class(class) => {
+                self.push(Inst::Class(class.clone()))?;
+            }
+            Node::Look(look) => {
+                self.push(Inst::Assert(*look))?;
+            }
+            Node::Group(node, None) => self.compile(node)?,
+            Node::Group(node, Some(index)) => {
+                self.push(Inst::Save(index * 2))?;
+                self.compile(node)?;
+                self.push(Inst::Save(index * 2 + 1))?;
+            }
+            Node::Concat(nodes) => {
+                for node in nodes {
+                    self.compile(node)?;
+                }
+            }
+            Node::Alternate(nodes) => {
+                let mut jumps = Vec::new();
+                for (i, node) in nodes.iter().enumerate() {
+                    if i + 1 == nodes.len() {
+                        self.compile(node)?;
+                    } else {
+                        let split = self.push(Inst::Split(0, 0))?;
+                        self.compile(node)?;
+                        jumps.push(self.push(Inst::Jmp(0))?);
+                        self.insts[split] = Inst::Split(split + 1, self.next_pc());
+                    }
+                }
+                let end = self.next_pc();
+                for jump in jumps {
+                    self.insts[jump] = Inst::Jmp(end);
+                }
+            }
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => {
+                for _ in 0..*min {
+                    self.compile(node)?;
+                }
+                match max {
+                    None => {
+                        let split = self.push(Inst::Split(0, 0))?;
+                        self.compile(node)?;
+                        self.push(Inst::Jmp(split))?;
+                        self.insts[split] = self.split(*greedy, split + 1, self.next_pc());
+                    }
+                    Some(max) => {
+                        let mut splits = Vec::new();
+                        for _ in *min..*max {
+                            splits.push(self.push(Inst::Split(0, 0))?);
+                            self.compile(node)?;
+                        }
+                        let end = self.next_pc();
+                        for split in splits {
+                            self.insts[split] = self.split(*greedy, split + 1, end);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn split(&self, greedy: bool, body: usize, skip: usize) -> Inst {
+        if greedy {
+            Inst::Split(body, skip)
+        } else {
+            Inst::Split(skip, body)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(pattern: &str, text: &str) -> Option<(usize, usize)> {
+        Regex::new(pattern).unwrap().find(text)
+    }
+
+    #[test]
+    fn anchors() {
+        let re = Regex::new("^fn .*Result").unwrap();
+        assert!(re.is_match("fn build() -> Result<Config, &'static str>"));
+        assert!(!re.is_match("pub fn build() -> Result<Config, &'static str>"));
+
+        assert_eq!(find("three.$", "Pick three."), Some((5, 11)));
+        assert_eq!(find("^$", ""), Some((0, 0)));
+        assert_eq!(find("\\bduct\\b", "productive duct tape"), Some((11, 15)));
+    }
+
+    #[test]
+    fn multi_line_anchors() {
+        let text = "fn one(\r\n    x: u32,\n) {}\nfn two() {}";
+        let re = Regex::build_multi_line("^fn \\w+\\($", false).unwrap();
+        assert_eq!(re.find(text), Some((0, 7)));
+        let re = Regex::build_multi_line("^fn t", false).unwrap();
+        assert_eq!(re.find(text), Some((26, 30)));
+        let re = Regex::build_multi_line("u32,\\n\\)", false).unwrap();
+        assert_eq!(re.find(text), Some((16, 22)));
+        // `.` still stops at line ends.
+        assert!(!Regex::build_multi_line("x.*two", false)
+            .unwrap()
+            .is_match(text));
+        assert!(!Regex::new("^fn t").unwrap().is_match(text));
+    }
+
+    #[test]
+    fn classes() {
+        assert_eq!(find("[0-9]+", "line 42!"), Some((5, 7)));
+        assert_eq!(find("[^a-z ]", "safe, fast"), Some((4, 5)));
+        assert_eq!(find("\\w+\\s\\d", "Pick 3"), Some((0, 6)));
+        assert_eq!(find("[-a]+", "x-a-y"), Some((1, 4)));
+        assert!(Regex::build("[q-s]UST", true).unwrap().is_match("trust"));
+    }
+
+    #[test]
+    fn repetition() {
+        assert_eq!(find("ab*c", "ac abbbc"), Some((0, 2)));
+        assert_eq!(find("ab+c", "ac abbbc"), Some((3, 8)));
+        assert_eq!(find("colou?r", "color"), Some((0, 5)));
+        assert_eq!(find("a{2,3}", "a aaaa"), Some((2, 5)));
+        assert_eq!(find("a{2}", "a aaaa"), Some((2, 4)));
+        assert_eq!(find("<.+>", "<a><b>"), Some((0, 6)));
+        assert_eq!(find("<.+?>", "<a><b>"), Some((0, 3)));
+        assert_eq!(find("x{", "x{"), Some((0, 2)));
+        // Nested empty loops must terminate.
+        assert_eq!(find("(a*)*b", "aab"), Some((0, 3)));
+    }
+
+    #[test]
+    fn alternation() {
+        assert_eq!(find("cat|dog", "hotdog"), Some((3, 6)));
+        assert_eq!(find("^(fn|struct) ", "struct Config"), Some((0, 7)));
+        // Leftmost-first: earlier branches win at the same position.
+        assert_eq!(find("a|ab", "ab"), Some((0, 1)));
+        assert_eq!(find("(?:Rust|Trust):?", "Trust me."), Some((0, 5)));
+    }
+
+    #[test]
+    fn whole_words() {
+        let re = Regex::build_words("ab|abc", false, false).unwrap();
+        assert_eq!(Some((0, 3)), re.find("abc"));
+        assert_eq!(Some((5, 7)), re.find("abcd ab"));
+        assert_eq!(None, re.find("xabc"));
+        // Only what is around a match counts, not what it starts with.
+        let re = Regex::build_words("-x", false, false).unwrap();
+        assert_eq!(Some((2, 4)), re.find("a -x"));
+        assert_eq!(None, re.find("a-x"));
+    }
+
+    #[test]
+    fn capture_groups() {
+        let re = Regex::new("(?P<key>\\w+)=(\\d+)?(?<rest>.*)").unwrap();
+        assert_eq!(3, re.group_count());
+        assert_eq!(Some(1), re.group_index("key"));
+        assert_eq!(Some(3), re.group_index("rest"));
+        assert_eq!(None, re.group_index("value"));
+
+        let caps = re.captures_at("  port=;", 0).unwrap();
+        assert_eq!(Some((2, 8)), caps.get(0));
+        assert_eq!(Some((2, 6)), caps.get(1));
+        assert_eq!(None, caps.get(2));
+        assert_eq!(Some((7, 8)), caps.get(3));
+        assert_eq!(None, caps.get(4));
+    }
+
+    #[test]
+    fn unicode_offsets() {
+        assert_eq!(find("é+", "café é"), Some((3, 5)));
+        assert_eq!(find(".$", "naïve"), Some((5, 6)));
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        for pattern in [
+            "(abc",
+            "abc)",
+            "[a-",
+            "*a",
+            "a{3,1}",
+            "\\q",
+            "a\\",
+            "a{1001}",
+            "(?<>a)",
+            "(?P<1a>a)",
+            "(?<a>x)(?<a>y)",
+            "(?<a",
+        ] {
+            assert!(Regex::new(pattern).is_err(), "{pattern} should not compile");
+        }
+    }
+}
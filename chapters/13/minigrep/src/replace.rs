@@ -0,0 +1,211 @@
+// Replacement text for `--replace`.
+//
+// In regex mode the text may refer to what the pattern captured: `$1` or
+// `${1}` for a numbered group, `${name}` for a named one, `$0` for the whole
+// match and `$$` for a literal dollar sign. Any other `$` is kept as is. With
+// literal patterns the text is used exactly as given.
+
+use crate::matcher::Matcher;
+use crate::regex::{Captures, Regex, RegexError};
+
+#[derive(Debug)]
+enum Piece {
+    Literal(String),
+    Group(usize),
+}
+
+#[derive(Debug)]
+pub struct Replacement {
+    pieces: Vec<Piece>,
+}
+
+impl Replacement {
+    pub fn literal(text: &str) -> Replacement {
+        Replacement {
+            pieces: vec![Piece::Literal(text.to_string())],
+        }
+    }
+
+    /// Parses `template`, checking every group it refers to against `regex`.
+    pub fn parse(template: &str, regex: &Regex) -> Result<Replacement, RegexError> {
+        let mut pieces = Vec::new();
+        let mut literal = String::new();
+        let mut rest = template;
+
+        while let Some(i) = rest.find('$') {
+            literal.push_str(&rest[..i]);
+            rest = &rest[i + 1..];
+
+            let (reference, after) = if let Some(braced) = rest.strip_prefix('{') {
+                let end = braced
+                    .find('}')
+                    .ok_or_else(|| RegexError::new("unclosed '${' in replacement"))?;
+                (&braced[..end], &braced[end + 1..])
+            } else if rest.starts_with('$') {
+                literal.push('$');
+                rest = &rest[1..];
+                continue;
+            } else {
+                let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+                if digits == 0 {
+                    literal.push('$');
+                    continue;
+                }
+                rest.split_at(digits)
+            };
+            rest = after;
+
+            let group = match reference.parse::<usize>() {
+                Ok(index) if index <= regex.group_count() => Some(index),
+                Ok(_) => None,
+                Err(_) => regex.group_index(reference),
+            }
+            .ok_or_else(|| {
+                RegexError::new(format!("no group '{reference}' for the replacement"))
+            })?;
+            if !literal.is_empty() {
+                pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+            }
+            pieces.push(Piece::Group(group));
+        }
+
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            pieces.push(Piece::Literal(literal));
+        }
+        Ok(Replacement { pieces })
+    }
+
+    /// Appends the replacement for one match of `line` to `dst`. Groups that
+    /// took no part in the match expand to nothing.
+    pub fn expand(&self, line: &str, caps: &Captures, dst: &mut String) {
+        for piece in &self.pieces {
+            match piece {
+                Piece::Literal(text) => dst.push_str(text),
+                Piece::Group(group) => {
+                    if let Some((start, end)) = caps.get(*group) {
+                        dst.push_str(&line[start..end]);
+                    }
+                }
+            }
+        }
+    }
+
+    /// `line` with every non-empty match of `matcher` replaced, and the byte
+    /// ranges the replacements took up in the new line. None if nothing
+    /// matched.
+    pub fn replace_all(
+        &self,
+        matcher: &Matcher,
+        line: &str,
+    ) -> Option<(String, Vec<(usize, usize)>)> {
+        let mut replaced = String::with_capacity(line.len());
+        let mut ranges = Vec::new();
+        let mut copied = 0;
+        let mut start = 0;
+
+        while let Some(caps) = matcher.captures_at(line, start) {
+            let (from, to) = caps.get(0)?;
+            if from == to {
+                // Step over an empty match, like `find_iter`.
+                match line[from..].chars().next() {
+                    Some(c) => start = from + c.len_utf8(),
+                    None => break,
+                }
+                continue;
+            }
+            replaced.push_str(&line[copied..from]);
+            let begin = replaced.len();
+            self.expand(line, &caps, &mut replaced);
+            ranges.push((begin, replaced.len()));
+            copied = to;
+            start = to;
+        }
+
+        if ranges.is_empty() {
+            return None;
+        }
+        replaced.push_str(&line[copied..]);
+        Some((replaced, ranges))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    fn replace(patterns: &[&str], regex: bool, template: &str, line: &str) -> Option<String> {
+        let config = Config {
+            patterns: patterns.iter().map(|p| p.to_string()).collect(),
+            regex,
+            ..Config::default()
+        };
+        let matcher = Matcher::new(&config).unwrap();
+        let replacement = matcher.replacement(template).unwrap();
+        replacement
+            .replace_all(&matcher, line)
+            .map(|(replaced, _)| replaced)
+    }
+
+    #[test]
+    fn literal_text() {
+        assert_eq!(
+            Some("safe, fast, pro$1ive.".to_string()),
+            replace(&["duct"], false, "$1", "safe, fast, productive.")
+        );
+        assert_eq!(None, replace(&["duct"], false, "x", "Pick three."));
+    }
+
+    #[test]
+    fn group_references() {
+        let line = "fn build(args: &[String]) -> Result<Config, &'static str>";
+        assert_eq!(
+            Some("fn build(args: &[String]) -> Result<Config, Error>".to_string()),
+            replace(&["Result<(\\w+), [^>]+>"], true, "Result<$1, Error>", line)
+        );
+        assert_eq!(
+            Some("let port = 8080; let $ = 1;".to_string()),
+            replace(
+                &["(?<key>\\w+)=(?P<value>\\d+)"],
+                true,
+                "let ${key} = ${2}; let $$ = 1;",
+                "port=8080"
+            )
+        );
+        // Every match is replaced; groups that didn't match are empty.
+        assert_eq!(
+            Some("<a> <> <c>".to_string()),
+            replace(&["(a)|b|(c)"], true, "<$1$2>", "a b c")
+        );
+        assert_eq!(
+            Some("[aa]b[aa]".to_string()),
+            replace(&["a*"], true, "[$0$0]", "aba")
+        );
+    }
+
+    #[test]
+    fn replaced_ranges() {
+        let matcher = Matcher::new(&Config {
+            patterns: vec!["a".to_string()],
+            ..Config::default()
+        })
+        .unwrap();
+        let (line, ranges) = Replacement::literal("xyz")
+            .replace_all(&matcher, "banana")
+            .unwrap();
+        assert_eq!("bxyznxyznxyz", line);
+        assert_eq!(vec![(1, 4), (5, 8), (9, 12)], ranges);
+    }
+
+    #[test]
+    fn invalid_references() {
+        let regex = Regex::new("(?<word>\\w+) (\\d)").unwrap();
+        for template in ["$3", "${name}", "${word", "${}"] {
+            assert!(
+                Replacement::parse(template, &regex).is_err(),
+                "{template} should be rejected"
+            );
+        }
+    }
+}
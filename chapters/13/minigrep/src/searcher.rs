@@ -0,0 +1,929 @@
+// The library's entry point for embedding minigrep: a `Searcher` decides
+// which lines of an input are selected (and which are context), and reports
+// them as events to a `Sink`, which decides what to do with them. The CLI's
+// output is just one such sink (see `printer`).
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::matcher::Matcher;
+use crate::memmem::{self, Finder};
+use crate::regex::RegexError;
+use crate::replace::Replacement;
+use crate::{gzip, search_reader, Config};
+
+/// Receives the results of a search, one input at a time: `begin`, then
+/// the selected and context lines in file order, then `end`. With
+/// --multiline, one `matched` call can cover several lines.
+pub trait Sink {
+    /// Called before anything is read from the input called `name`.
+    fn begin(&mut self, _name: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// A selected line. Returning `Ok(false)` stops searching this input
+    /// (`end` is still called).
+    fn matched(&mut self, line: &SinkMatch<'_>) -> io::Result<bool>;
+
+    /// A line of context around a selected line.
+    fn context(&mut self, _line: &SinkContext<'_>) -> io::Result<bool> {
+        Ok(true)
+    }
+
+    /// Called between two groups of lines that aren't adjacent in the
+    /// input, when context was asked for.
+    fn context_break(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called once the input has been searched.
+    fn end(&mut self, _name: &str, _summary: &FileSummary) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct SinkMatch<'a> {
+    pub line_number: usize,
+    /// The number of the last line in `line`. Only differs from
+    /// `line_number` with --multiline, where a match can span lines and
+    /// `line` holds all of them, separated by their line breaks.
+    pub end_line_number: usize,
+    pub line: &'a str,
+    // What the matcher looks at: `line`, plus its line break with
+    // --multiline, since a match can take that in too.
+    haystack: &'a str,
+    /// With --fuzzy, how many edits the line is from the closest pattern.
+    pub distance: Option<usize>,
+    // None for inverted searches, where there's nothing to point at.
+    matcher: Option<&'a Matcher>,
+    replacement: Option<&'a Replacement>,
+}
+
+impl SinkMatch<'_> {
+    /// Byte ranges of the matches in `line`, computed on demand since most
+    /// sinks don't need them.
+    pub fn matches(&self) -> Vec<(usize, usize)> {
+        let Some(matcher) = self.matcher else {
+            return Vec::new();
+        };
+        let len = self.line.len();
+        let mut matches = matcher.find_iter(self.haystack);
+        matches.retain(|&(start, _)| start < len);
+        for (_, end) in &mut matches {
+            *end = (*end).min(len);
+        }
+        matches
+    }
+
+    /// `line` with every match replaced by the `--replace` text, and the
+    /// byte ranges of the replacements in it. None without `--replace` or
+    /// in an inverted search.
+    pub fn replaced(&self) -> Option<(String, Vec<(usize, usize)>)> {
+        let (mut replaced, mut ranges) = self
+            .replacement?
+            .replace_all(self.matcher?, self.haystack)?;
+        let line_break = &self.haystack[self.line.len()..];
+        if replaced.ends_with(line_break) {
+            replaced.truncate(replaced.len() - line_break.len());
+            for (start, end) in &mut ranges {
+                *start = (*start).min(replaced.len());
+                *end = (*end).min(replaced.len());
+            }
+        }
+        Some((replaced, ranges))
+    }
+}
+
+#[derive(Debug)]
+pub struct SinkContext<'a> {
+    pub line_number: usize,
+    pub line: &'a str,
+}
+
+/// What `Sink::end` learns about the input as a whole.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FileSummary {
+    pub matched_lines: usize,
+    /// The matches in the selected lines, if the searcher was asked to
+    /// count them (--count-matches or --stats); otherwise 0.
+    pub matches: usize,
+    /// How much of the input was read, after decompression. Less than all
+    /// of it if the sink stopped the search early.
+    pub bytes_searched: u64,
+    pub elapsed: Duration,
+}
+
+/// Totals over several inputs, for --stats.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Stats {
+    pub files_searched: usize,
+    pub files_matched: usize,
+    pub matched_lines: usize,
+    pub matches: usize,
+    pub bytes_searched: u64,
+    /// Time spent searching, summed over the inputs (so with several
+    /// threads it can be more than the run took).
+    pub elapsed: Duration,
+}
+
+impl Stats {
+    pub fn add(&mut self, summary: &FileSummary) {
+        self.files_searched += 1;
+        self.files_matched += usize::from(summary.matched_lines > 0);
+        self.matched_lines += summary.matched_lines;
+        self.matches += summary.matches;
+        self.bytes_searched += summary.bytes_searched;
+        self.elapsed += summary.elapsed;
+    }
+}
+
+// How much more of the input the literal fast path reads at a time.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+pub struct Searcher {
+    matcher: Matcher,
+    invert_match: bool,
+    before_context: usize,
+    after_context: usize,
+    replacement: Option<Replacement>,
+    // --fuzzy lists the closest lines first.
+    rank: bool,
+    multiline: bool,
+    count_matches: bool,
+}
+
+impl Searcher {
+    pub fn new(config: &Config) -> Result<Searcher, RegexError> {
+        let rank = config.fuzzy.is_some() && !config.invert_match;
+        // -c and -l never print lines, so context would only be wasted, and
+        // ranked lines are out of order, so context makes no sense there.
+        let context = !config.count && !config.count_matches && !config.files_with_matches && !rank;
+        let matcher = Matcher::new(config)?;
+        let replacement = match &config.replace {
+            Some(template) => Some(matcher.replacement(template)?),
+            None => None,
+        };
+        Ok(Searcher {
+            matcher,
+            invert_match: config.invert_match,
+            before_context: if context { config.before_context } else { 0 },
+            after_context: if context { config.after_context } else { 0 },
+            replacement,
+            rank,
+            multiline: config.multiline,
+            count_matches: config.count_matches || config.stats,
+        })
+    }
+
+    /// Lines of context shown before and after each selected line.
+    pub fn context(&self) -> (usize, usize) {
+        (self.before_context, self.after_context)
+    }
+
+    /// `line` with every match replaced by the `--replace` text, or None if
+    /// there's no replacement to make.
+    pub fn replace_line(&self, line: &str) -> Option<String> {
+        let (replaced, _) = self
+            .replacement
+            .as_ref()?
+            .replace_all(&self.matcher, line)?;
+        Some(replaced)
+    }
+
+    /// Searches the file at `path`, naming it by its path. gzip files are
+    /// decompressed first.
+    pub fn search_path(&self, path: &Path, sink: &mut impl Sink) -> io::Result<FileSummary> {
+        let reader = gzip::maybe_decompress(BufReader::new(File::open(path)?), false)?;
+        self.search_reader(&path.display().to_string(), reader, sink)
+    }
+
+    /// Searches everything `reader` yields, as the input called `name`.
+    /// Returns the summary `sink` got at the end.
+    pub fn search_reader(
+        &self,
+        name: &str,
+        reader: impl BufRead,
+        sink: &mut impl Sink,
+    ) -> io::Result<FileSummary> {
+        let started = Instant::now();
+        let mut reader = Counted {
+            inner: reader,
+            bytes: 0,
+        };
+        sink.begin(name)?;
+        let mut summary = if self.multiline {
+            self.search_multiline(&mut reader, sink)?
+        } else if self.rank {
+            self.search_ranked(&mut reader, sink)?
+        } else if let Some(finder) = self.literal() {
+            self.search_literal(finder, &mut reader, sink)?
+        } else {
+            self.search_in_order(&mut reader, sink)?
+        };
+        summary.bytes_searched = reader.bytes;
+        summary.elapsed = started.elapsed();
+        sink.end(name, &summary)?;
+        Ok(summary)
+    }
+
+    // Passes a selected line (or --multiline block) on to `sink`, and adds
+    // it to `summary`.
+    fn report(
+        &self,
+        sink: &mut impl Sink,
+        line: &SinkMatch<'_>,
+        summary: &mut FileSummary,
+    ) -> io::Result<bool> {
+        summary.matched_lines += line.end_line_number + 1 - line.line_number;
+        if self.count_matches {
+            summary.matches += line.matches().len();
+        }
+        sink.matched(line)
+    }
+
+    // The matcher's literal, when nothing but the lines containing it is
+    // wanted.
+    fn literal(&self) -> Option<&Finder> {
+        let plain = !self.invert_match && self.before_context == 0 && self.after_context == 0;
+        self.matcher.literal().filter(|_| plain)
+    }
+
+    // Reads the input a chunk at a time and scans each chunk for the
+    // literal as a whole, only working out where lines begin and end (and
+    // their numbers) around the hits.
+    fn search_literal(
+        &self,
+        finder: &Finder,
+        mut reader: impl BufRead,
+        sink: &mut impl Sink,
+    ) -> io::Result<FileSummary> {
+        let mut buf = Vec::new();
+        // Number of the first line in `buf`.
+        let mut line_number = 1;
+        let mut summary = FileSummary::default();
+
+        loop {
+            let want = buf.len() + CHUNK_SIZE;
+            let eof = fill(&mut reader, &mut buf, want)?;
+            // A partial line at the end waits for the next chunk.
+            let end = if eof {
+                buf.len()
+            } else {
+                memmem::memrchr(b'\n', &buf).map_or(0, |i| i + 1)
+            };
+
+            let mut counted = 0;
+            for (start, line_end) in finder.find_lines(&buf[..end]) {
+                line_number += memmem::count(b'\n', &buf[counted..start]);
+                counted = start;
+                let mut line = &buf[start..line_end];
+                // Same line endings as `search_reader`: "\n" or "\r\n".
+                if line_end < end {
+                    line = line.strip_suffix(b"\r").unwrap_or(line);
+                }
+                let line = String::from_utf8_lossy(line);
+                let line = SinkMatch {
+                    line_number,
+                    end_line_number: line_number,
+                    line: &line,
+                    haystack: &line,
+                    distance: None,
+                    matcher: Some(&self.matcher),
+                    replacement: self.replacement.as_ref(),
+                };
+                let keep_going = self.report(sink, &line, &mut summary)?;
+                if !keep_going {
+                    return Ok(summary);
+                }
+            }
+
+            if eof {
+                return Ok(summary);
+            }
+            line_number += memmem::count(b'\n', &buf[counted..end]);
+            buf.drain(..end);
+        }
+    }
+
+    fn search_in_order(
+        &self,
+        reader: impl BufRead,
+        sink: &mut impl Sink,
+    ) -> io::Result<FileSummary> {
+        let show_breaks = self.before_context > 0 || self.after_context > 0;
+        let mut last_line_number = None;
+        let mut summary = FileSummary::default();
+        search_reader(
+            reader,
+            self.before_context,
+            self.after_context,
+            |line| self.matcher.is_match(line) != self.invert_match,
+            |record| {
+                if show_breaks
+                    && last_line_number.is_some_and(|last| last + 1 != record.line_number)
+                {
+                    sink.context_break()?;
+                }
+                last_line_number = Some(record.line_number);
+
+                if record.is_context {
+                    return sink.context(&SinkContext {
+                        line_number: record.line_number,
+                        line: record.line,
+                    });
+                }
+                let line = SinkMatch {
+                    line_number: record.line_number,
+                    end_line_number: record.line_number,
+                    line: record.line,
+                    haystack: record.line,
+                    distance: None,
+                    matcher: (!self.invert_match).then_some(&self.matcher),
+                    replacement: self.replacement.as_ref(),
+                };
+                self.report(sink, &line, &mut summary)
+            },
+        )?;
+        Ok(summary)
+    }
+
+    // Collects every hit before reporting any, closest first (and in file
+    // order among equally close ones).
+    fn search_ranked(&self, reader: impl BufRead, sink: &mut impl Sink) -> io::Result<FileSummary> {
+        let mut hits = Vec::new();
+        search_reader(
+            reader,
+            0,
+            0,
+            |line| self.matcher.is_match(line),
+            |record| {
+                hits.push(Hit {
+                    distance: self.matcher.distance(record.line).unwrap_or_default(),
+                    line_number: record.line_number,
+                    line: record.line.to_string(),
+                });
+                Ok(true)
+            },
+        )?;
+        hits.sort_by_key(|hit| (hit.distance, hit.line_number));
+
+        let mut summary = FileSummary::default();
+        for hit in &hits {
+            if !self.report(sink, &self.hit_match(hit), &mut summary)? {
+                break;
+            }
+        }
+        Ok(summary)
+    }
+
+    /// Whether hits are listed closest first rather than in file order.
+    pub fn ranks(&self) -> bool {
+        self.rank
+    }
+
+    /// Reports a hit that `Hits` kept from searching the input `name`, for
+    /// ranking the hits of several inputs together.
+    pub fn report_hit(&self, name: &str, hit: &Hit, sink: &mut impl Sink) -> io::Result<()> {
+        let mut summary = FileSummary::default();
+        sink.begin(name)?;
+        self.report(sink, &self.hit_match(hit), &mut summary)?;
+        sink.end(name, &summary)
+    }
+
+    fn hit_match<'h>(&'h self, hit: &'h Hit) -> SinkMatch<'h> {
+        SinkMatch {
+            line_number: hit.line_number,
+            end_line_number: hit.line_number,
+            line: &hit.line,
+            haystack: &hit.line,
+            distance: Some(hit.distance),
+            matcher: Some(&self.matcher),
+            replacement: self.replacement.as_ref(),
+        }
+    }
+
+    // Reads the whole input and matches against all of it at once, so that
+    // matches can run over line breaks. A match selects every line it
+    // touches, and matches that share a line select them as one block.
+    fn search_multiline(
+        &self,
+        mut reader: impl BufRead,
+        sink: &mut impl Sink,
+    ) -> io::Result<FileSummary> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let text = String::from_utf8_lossy(&bytes);
+        let lines = line_ranges(&text);
+        // The line holding byte `pos`.
+        let line_at = |pos: usize| lines.partition_point(|&(start, _)| start <= pos) - 1;
+
+        // The selected blocks, as ranges of line indexes.
+        let mut blocks: Vec<(usize, usize)> = Vec::new();
+        let mut start = 0;
+        while let Some((from, to)) = self.matcher.find_at(&text, start) {
+            if lines.is_empty() {
+                break;
+            }
+            let first = line_at(from);
+            let end = line_at(to.saturating_sub(1).max(from)) + 1;
+            match blocks.last_mut() {
+                Some(block) if first < block.1 => block.1 = block.1.max(end),
+                _ => blocks.push((first, end)),
+            }
+            start = if to > from {
+                to
+            } else {
+                // Step over an empty match so the loop makes progress.
+                match text[from..].chars().next() {
+                    Some(c) => from + c.len_utf8(),
+                    None => break,
+                }
+            };
+        }
+        if self.invert_match {
+            let mut next = 0;
+            let mut unmatched = Vec::new();
+            for &(first, end) in blocks.iter().chain([&(lines.len(), lines.len())]) {
+                unmatched.extend((next..first).map(|i| (i, i + 1)));
+                next = end;
+            }
+            blocks = unmatched;
+        }
+
+        let show_breaks = self.before_context > 0 || self.after_context > 0;
+        let mut summary = FileSummary::default();
+        // The first line after the last block.
+        let mut next = 0;
+        for (i, &(first, end)) in blocks.iter().enumerate() {
+            let mut context_from = first.saturating_sub(self.before_context);
+            if i > 0 {
+                let after = (next + self.after_context).min(first);
+                if !report_context(sink, &text, &lines, next..after)? {
+                    return Ok(summary);
+                }
+                if show_breaks && context_from > after {
+                    sink.context_break()?;
+                }
+                context_from = context_from.max(after);
+            }
+            if !report_context(sink, &text, &lines, context_from..first)? {
+                return Ok(summary);
+            }
+
+            let block_start = lines[first].0;
+            // Up to where the next line starts, taking in the line break.
+            let block_end = lines.get(end).map_or(text.len(), |&(start, _)| start);
+            let block = SinkMatch {
+                line_number: first + 1,
+                end_line_number: end,
+                line: &text[block_start..lines[end - 1].1],
+                haystack: &text[block_start..block_end],
+                distance: None,
+                matcher: (!self.invert_match).then_some(&self.matcher),
+                replacement: self.replacement.as_ref(),
+            };
+            if !self.report(sink, &block, &mut summary)? {
+                return Ok(summary);
+            }
+            next = end;
+        }
+        if !blocks.is_empty() {
+            let after = (next + self.after_context).min(lines.len());
+            report_context(sink, &text, &lines, next..after)?;
+        }
+        Ok(summary)
+    }
+}
+
+// The byte range of each line in `text`, leaving out its "\n" or "\r\n".
+fn line_ranges(text: &str) -> Vec<(usize, usize)> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    while start < text.len() {
+        let Some(i) = memmem::memchr(b'\n', &text.as_bytes()[start..]) else {
+            lines.push((start, text.len()));
+            break;
+        };
+        let end = start + i;
+        let content = text[start..end].strip_suffix('\r').map_or(i, str::len);
+        lines.push((start, start + content));
+        start = end + 1;
+    }
+    lines
+}
+
+// Reports the lines `range` of `text` as context. Returns whether `sink`
+// wants more.
+fn report_context(
+    sink: &mut impl Sink,
+    text: &str,
+    lines: &[(usize, usize)],
+    range: std::ops::Range<usize>,
+) -> io::Result<bool> {
+    for i in range {
+        let (start, end) = lines[i];
+        let keep_going = sink.context(&SinkContext {
+            line_number: i + 1,
+            line: &text[start..end],
+        })?;
+        if !keep_going {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// A selected line held on to with its --fuzzy distance, to be ranked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hit {
+    pub distance: usize,
+    pub line_number: usize,
+    pub line: String,
+}
+
+/// Keeps the selected lines rather than printing them, for
+/// `Searcher::report_hit` to report once all inputs have been searched.
+#[derive(Debug, Default)]
+pub struct Hits(pub Vec<Hit>);
+
+impl Sink for Hits {
+    fn matched(&mut self, line: &SinkMatch<'_>) -> io::Result<bool> {
+        self.0.push(Hit {
+            distance: line.distance.unwrap_or_default(),
+            line_number: line.line_number,
+            line: line.line.to_string(),
+        });
+        Ok(true)
+    }
+}
+
+/// Passes everything on to `inner`, with `offset` added to every line
+/// number: for searching a piece of an input that starts further in.
+pub(crate) struct Renumbered<'s, S> {
+    pub inner: &'s mut S,
+    pub offset: usize,
+}
+
+impl<S: Sink> Sink for Renumbered<'_, S> {
+    fn begin(&mut self, name: &str) -> io::Result<()> {
+        self.inner.begin(name)
+    }
+
+    fn matched(&mut self, line: &SinkMatch<'_>) -> io::Result<bool> {
+        self.inner.matched(&SinkMatch {
+            line_number: line.line_number + self.offset,
+            end_line_number: line.end_line_number + self.offset,
+            ..*line
+        })
+    }
+
+    fn context(&mut self, line: &SinkContext<'_>) -> io::Result<bool> {
+        self.inner.context(&SinkContext {
+            line_number: line.line_number + self.offset,
+            line: line.line,
+        })
+    }
+
+    fn context_break(&mut self) -> io::Result<()> {
+        self.inner.context_break()
+    }
+
+    fn end(&mut self, name: &str, summary: &FileSummary) -> io::Result<()> {
+        self.inner.end(name, summary)
+    }
+}
+
+// Counts the bytes the search takes from a reader.
+struct Counted<R> {
+    inner: R,
+    bytes: u64,
+}
+
+impl<R: Read> Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.bytes += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for Counted<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.bytes += amount as u64;
+        self.inner.consume(amount);
+    }
+}
+
+// Appends what `reader` has to `buf` until it holds at least `len` bytes.
+// Returns whether the input ran out first.
+fn fill(reader: &mut impl BufRead, buf: &mut Vec<u8>, len: usize) -> io::Result<bool> {
+    while buf.len() < len {
+        let available = match reader.fill_buf() {
+            Ok(available) => available,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if available.is_empty() {
+            return Ok(true);
+        }
+        let n = available.len();
+        buf.extend_from_slice(available);
+        reader.consume(n);
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POEM: &str = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Duct tape.
+Trust me.";
+
+    // Records every event as a line of text.
+    #[derive(Default)]
+    struct Events {
+        events: Vec<String>,
+        // Stop each input after this many selected lines.
+        limit: Option<usize>,
+    }
+
+    impl Sink for Events {
+        fn begin(&mut self, name: &str) -> io::Result<()> {
+            self.events.push(format!("begin {name}"));
+            Ok(())
+        }
+
+        fn matched(&mut self, line: &SinkMatch<'_>) -> io::Result<bool> {
+            let mut event = format!("match {}", line.line_number);
+            if line.end_line_number != line.line_number {
+                event += &format!("-{}", line.end_line_number);
+            }
+            event += &format!(":{} {:?}", line.line, line.matches());
+            if let Some(distance) = line.distance {
+                event += &format!(" ~{distance}");
+            }
+            self.events.push(event);
+            let selected = self
+                .events
+                .iter()
+                .filter(|e| e.starts_with("match"))
+                .count();
+            Ok(self.limit.is_none_or(|limit| selected < limit))
+        }
+
+        fn context(&mut self, line: &SinkContext<'_>) -> io::Result<bool> {
+            self.events
+                .push(format!("context {}:{}", line.line_number, line.line));
+            Ok(true)
+        }
+
+        fn context_break(&mut self) -> io::Result<()> {
+            self.events.push("--".to_string());
+            Ok(())
+        }
+
+        fn end(&mut self, name: &str, summary: &FileSummary) -> io::Result<()> {
+            self.events
+                .push(format!("end {name} {}", summary.matched_lines));
+            Ok(())
+        }
+    }
+
+    fn events(config: Config, limit: Option<usize>) -> Vec<String> {
+        let searcher = Searcher::new(&config).unwrap();
+        let mut sink = Events {
+            limit,
+            ..Events::default()
+        };
+        searcher
+            .search_reader("poem.txt", POEM.as_bytes(), &mut sink)
+            .unwrap();
+        sink.events
+    }
+
+    fn config(patterns: &[&str]) -> Config {
+        Config {
+            patterns: patterns.iter().map(|p| p.to_string()).collect(),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn matches_and_context() {
+        let config = Config {
+            ignore_case: true,
+            after_context: 1,
+            ..config(&["rust"])
+        };
+        assert_eq!(
+            vec![
+                "begin poem.txt",
+                "match 1:Rust: [(0, 4)]",
+                "context 2:safe, fast, productive.",
+                "--",
+                "match 5:Trust me. [(1, 5)]",
+                "end poem.txt 2",
+            ],
+            events(config, None)
+        );
+    }
+
+    #[test]
+    fn inverted() {
+        let config = Config {
+            invert_match: true,
+            ..config(&["e"])
+        };
+        assert_eq!(
+            vec!["begin poem.txt", "match 1:Rust: []", "end poem.txt 1"],
+            events(config, None)
+        );
+    }
+
+    #[test]
+    fn sink_stops_early() {
+        assert_eq!(
+            vec![
+                "begin poem.txt",
+                "match 2:safe, fast, productive. [(6, 10)]",
+                "end poem.txt 1",
+            ],
+            events(config(&["fast", "tape"]), Some(1))
+        );
+    }
+
+    #[test]
+    fn fuzzy_ranking() {
+        let config = Config {
+            fuzzy: Some(2),
+            before_context: 1,
+            ..config(&["Trust"])
+        };
+        assert_eq!(
+            vec![
+                "begin poem.txt",
+                "match 5:Trust me. [(0, 5)] ~0",
+                "match 1:Rust: [(1, 4)] ~2",
+                "end poem.txt 2",
+            ],
+            events(config, None)
+        );
+    }
+
+    #[test]
+    fn counting_skips_context() {
+        let config = Config {
+            count: true,
+            before_context: 2,
+            ..config(&["Duct"])
+        };
+        assert_eq!(
+            vec![
+                "begin poem.txt",
+                "match 4:Duct tape. [(0, 4)]",
+                "end poem.txt 1"
+            ],
+            events(config, None)
+        );
+    }
+
+    #[test]
+    fn literal_fast_path() {
+        // Several chunks' worth, with a line longer than a chunk, "\r\n"
+        // endings, invalid UTF-8 and no newline at the end.
+        let mut input = Vec::new();
+        for i in 0..20_000 {
+            input.extend_from_slice(format!("line {i}: ").as_bytes());
+            if i % 7 == 0 {
+                input.extend_from_slice(b"productive \xff");
+            }
+            if i == 9_000 {
+                input.extend_from_slice(&[b'x'; 3 * CHUNK_SIZE]);
+                input.extend_from_slice(b"productive");
+            }
+            input.extend_from_slice(if i % 3 == 0 { b"\r\n" } else { b"\n" });
+        }
+        input.extend_from_slice(b"productive\r");
+
+        let searcher = Searcher::new(&config(&["productive"])).unwrap();
+        assert!(searcher.literal().is_some());
+        let mut fast = Events::default();
+        searcher
+            .search_reader("big", &input[..], &mut fast)
+            .unwrap();
+
+        let mut slow = Events::default();
+        slow.begin("big").unwrap();
+        let summary = searcher.search_in_order(&input[..], &mut slow).unwrap();
+        slow.end("big", &summary).unwrap();
+        assert_eq!(slow.events, fast.events);
+        assert_eq!(2_860, summary.matched_lines);
+
+        // Context needs the lines in between, so it takes the slow path.
+        let with_context = Config {
+            after_context: 1,
+            ..config(&["productive"])
+        };
+        assert!(Searcher::new(&with_context).unwrap().literal().is_none());
+    }
+
+    #[test]
+    fn summaries() {
+        let counting = Config {
+            count_matches: true,
+            ..config(&["t"])
+        };
+        for config in [
+            counting,
+            Config {
+                regex: true,
+                count_matches: true,
+                ..config(&["t"])
+            },
+        ] {
+            let searcher = Searcher::new(&config).unwrap();
+            let summary = searcher
+                .search_reader("poem.txt", POEM.as_bytes(), &mut Events::default())
+                .unwrap();
+            assert_eq!(
+                (5, 7, POEM.len() as u64),
+                (
+                    summary.matched_lines,
+                    summary.matches,
+                    summary.bytes_searched
+                )
+            );
+        }
+
+        // Matches are only counted when asked for.
+        let searcher = Searcher::new(&config(&["t"])).unwrap();
+        let summary = searcher
+            .search_reader("poem.txt", POEM.as_bytes(), &mut Events::default())
+            .unwrap();
+        assert_eq!((5, 0), (summary.matched_lines, summary.matches));
+    }
+
+    #[test]
+    fn multiline() {
+        let literals = Config {
+            multiline: true,
+            before_context: 1,
+            ..config(&["productive.\nPick", "three.\nDuct t"])
+        };
+        assert_eq!(
+            vec![
+                "begin poem.txt",
+                "context 1:Rust:",
+                "match 2-4:safe, fast, productive.\nPick three.\nDuct tape. [(12, 28), (29, 42)]",
+                "end poem.txt 3",
+            ],
+            events(literals, None)
+        );
+
+        let regex = Config {
+            multiline: true,
+            regex: true,
+            after_context: 1,
+            ..config(&["^\\w+:$", "^Duct.*\\n"])
+        };
+        assert_eq!(
+            vec![
+                "begin poem.txt",
+                "match 1:Rust: [(0, 5)]",
+                "context 2:safe, fast, productive.",
+                "--",
+                "match 4:Duct tape. [(0, 10)]",
+                "context 5:Trust me.",
+                "end poem.txt 2",
+            ],
+            events(regex, None)
+        );
+
+        let inverted = Config {
+            multiline: true,
+            invert_match: true,
+            ..config(&["fast", "three.\nDuct"])
+        };
+        assert_eq!(
+            vec![
+                "begin poem.txt",
+                "match 1:Rust: []",
+                "match 5:Trust me. []",
+                "end poem.txt 2",
+            ],
+            events(inverted, None)
+        );
+    }
+}
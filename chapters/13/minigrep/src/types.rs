@@ -0,0 +1,78 @@
+// File types for `--type` and `--type-not`: names for lists of file name
+// globs. The config file can add more, or redefine these.
+
+const BUILTIN: [(&str, &[&str]); 24] = [
+    ("c", &["*.c", "*.h"]),
+    (
+        "cpp",
+        &["*.cpp", "*.cc", "*.cxx", "*.hpp", "*.hh", "*.hxx", "*.h"],
+    ),
+    ("csharp", &["*.cs"]),
+    ("css", &["*.css", "*.scss", "*.sass", "*.less"]),
+    ("go", &["*.go"]),
+    ("html", &["*.html", "*.htm", "*.xhtml"]),
+    ("java", &["*.java"]),
+    ("js", &["*.js", "*.jsx", "*.mjs", "*.cjs"]),
+    ("json", &["*.json"]),
+    ("kotlin", &["*.kt", "*.kts"]),
+    ("lua", &["*.lua"]),
+    ("make", &["Makefile", "makefile", "GNUmakefile", "*.mk"]),
+    ("markdown", &["*.md", "*.markdown"]),
+    ("php", &["*.php"]),
+    ("py", &["*.py", "*.pyi"]),
+    ("ruby", &["*.rb", "Gemfile", "Rakefile"]),
+    ("rust", &["*.rs"]),
+    ("sh", &["*.sh", "*.bash", "*.zsh", ".bashrc", ".zshrc"]),
+    ("sql", &["*.sql"]),
+    ("swift", &["*.swift"]),
+    ("toml", &["*.toml", "Cargo.lock"]),
+    ("ts", &["*.ts", "*.tsx", "*.mts", "*.cts"]),
+    ("txt", &["*.txt"]),
+    ("yaml", &["*.yaml", "*.yml"]),
+];
+
+/// The globs for the file type `name`: the config file's definition of it
+/// (from `defined`) if there is one, otherwise the built-in one.
+pub fn globs<'a>(defined: &'a [(String, Vec<String>)], name: &str) -> Option<Vec<&'a str>> {
+    if let Some((_, globs)) = defined.iter().rev().find(|(other, _)| other == name) {
+        return Some(globs.iter().map(String::as_str).collect());
+    }
+    BUILTIN
+        .iter()
+        .find(|(other, _)| *other == name)
+        .map(|(_, globs)| globs.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::glob::Glob;
+
+    #[test]
+    fn lookup() {
+        let defined = vec![
+            (
+                "rust".to_string(),
+                vec!["*.rs".to_string(), "*.rs.in".to_string()],
+            ),
+            ("proto".to_string(), vec!["*.proto".to_string()]),
+        ];
+        assert_eq!(Some(vec!["*.rs", "*.rs.in"]), globs(&defined, "rust"));
+        assert_eq!(Some(vec!["*.proto"]), globs(&defined, "proto"));
+        assert_eq!(Some(vec!["*.yaml", "*.yml"]), globs(&defined, "yaml"));
+        assert_eq!(None, globs(&defined, "cobol"));
+    }
+
+    #[test]
+    fn builtin_globs_parse() {
+        for (name, globs) in BUILTIN {
+            for glob in globs {
+                assert!(Glob::new(glob).is_ok(), "{name}: {glob}");
+            }
+        }
+        let mut names: Vec<&str> = BUILTIN.iter().map(|(name, _)| *name).collect();
+        names.sort();
+        names.dedup();
+        assert_eq!(BUILTIN.len(), names.len());
+    }
+}
@@ -0,0 +1,452 @@
+// Recursive file enumeration for directory operands.
+//
+// Files are returned in a stable (sorted) order. `.gitignore` and `.ignore`
+// files found along the way are honored with gitignore semantics: patterns
+// without a slash match a name at any depth, patterns with one are relative
+// to the directory holding the ignore file, a trailing `/` only matches
+// directories, `!` re-includes, and the last matching rule wins (rules from
+// deeper directories come later).
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::glob::{Glob, GlobError};
+
+const IGNORE_FILES: [&str; 2] = [".gitignore", ".ignore"];
+
+// How much of a file is inspected when deciding whether it's binary.
+const BINARY_SNIFF_LEN: usize = 8 * 1024;
+
+/// What a walk found. Like grep, a directory or entry that can't be read
+/// doesn't stop the walk; it's left out of `files` and listed in `errors`.
+#[derive(Debug, Default)]
+pub struct Walk {
+    pub files: Vec<PathBuf>,
+    pub errors: Vec<(PathBuf, io::Error)>,
+}
+
+/// Every regular file under `root`, minus ignored ones. A `root` that is a
+/// file is returned as is.
+pub fn walk(root: &Path) -> Walk {
+    walk_filtered(root, &Filter::default())
+}
+
+/// Like `walk`, but files found under a directory must also pass `filter`.
+pub fn walk_filtered(root: &Path, filter: &Filter) -> Walk {
+    let mut walk = Walk::default();
+    if root.is_dir() {
+        let mut ignores = Vec::new();
+        walk_dir(root, root, filter, &mut ignores, &mut walk);
+    } else {
+        walk.files.push(root.to_path_buf());
+    }
+    walk
+}
+
+/// Which of the files found by walking a directory get searched, on top of
+/// what its ignore files say. Paths are matched relative to the directory
+/// being walked.
+#[derive(Debug, Default)]
+pub struct Filter {
+    // Gitignore-style rules for what to skip.
+    ignore: Vec<Rule>,
+    // `-g` globs: the last one that matches a path decides, `!` meaning
+    // skip it. If there are any without `!`, files have to match one.
+    globs: Vec<Rule>,
+    // File name globs from --type; if there are any, one has to match.
+    include: Vec<Glob>,
+    // And from --type-not; none of these may match.
+    exclude: Vec<Glob>,
+}
+
+impl Filter {
+    /// Skips what the gitignore-style `rule` matches.
+    pub fn ignore(&mut self, rule: &str) -> Result<(), GlobError> {
+        self.ignore.extend(Rule::parse(rule)?);
+        Ok(())
+    }
+
+    /// Adds a `-g` glob: only search the files it matches, or with a
+    /// leading `!`, skip them (and whole directories).
+    pub fn glob(&mut self, glob: &str) -> Result<(), GlobError> {
+        self.globs.extend(Rule::parse(glob)?);
+        Ok(())
+    }
+
+    /// Only searches files whose name matches `glob` (or one of the others
+    /// given this way).
+    pub fn include(&mut self, glob: &str) -> Result<(), GlobError> {
+        self.include.push(Glob::new(glob)?);
+        Ok(())
+    }
+
+    /// Skips files whose name matches `glob`.
+    pub fn exclude(&mut self, glob: &str) -> Result<(), GlobError> {
+        self.exclude.push(Glob::new(glob)?);
+        Ok(())
+    }
+
+    fn skips_dir(&self, relative: &str) -> bool {
+        is_ignored_by(&self.ignore, relative, true)
+            || last_match(&self.globs, relative, true).is_some_and(|rule| rule.negated)
+    }
+
+    fn includes_file(&self, relative: &str) -> bool {
+        if is_ignored_by(&self.ignore, relative, false) {
+            return false;
+        }
+        let selected = match last_match(&self.globs, relative, false) {
+            Some(rule) => !rule.negated,
+            None => self.globs.iter().all(|rule| rule.negated),
+        };
+        let name = relative.rsplit('/').next().unwrap_or(relative);
+        selected
+            && (self.include.is_empty() || self.include.iter().any(|glob| glob.is_match(name)))
+            && !self.exclude.iter().any(|glob| glob.is_match(name))
+    }
+}
+
+/// Heuristic used by grep and friends: a NUL byte near the start of the
+/// file means it isn't text.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes[..bytes.len().min(BINARY_SNIFF_LEN)].contains(&0)
+}
+
+fn walk_dir(
+    root: &Path,
+    dir: &Path,
+    filter: &Filter,
+    ignores: &mut Vec<IgnoreFile>,
+    walk: &mut Walk,
+) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            walk.errors.push((dir.to_path_buf(), err));
+            return;
+        }
+    };
+    let mut paths = Vec::new();
+    for entry in entries {
+        match entry {
+            Ok(entry) => paths.push(entry.path()),
+            Err(err) => walk.errors.push((dir.to_path_buf(), err)),
+        }
+    }
+    paths.sort();
+
+    let inherited = ignores.len();
+    ignores.extend(
+        IGNORE_FILES
+            .iter()
+            .filter_map(|name| IgnoreFile::read(dir, name)),
+    );
+
+    for path in paths {
+        if path.file_name().is_some_and(|name| name == ".git") {
+            continue;
+        }
+        // Entries can vanish while the walk is going on.
+        let file_type = match fs::symlink_metadata(&path) {
+            Ok(metadata) => metadata.file_type(),
+            Err(err) => {
+                walk.errors.push((path, err));
+                continue;
+            }
+        };
+        // Following symlinks could loop forever; only walk real entries.
+        if file_type.is_symlink() {
+            continue;
+        }
+        let is_dir = file_type.is_dir();
+        if is_ignored(ignores, &path, is_dir) {
+            continue;
+        }
+        let relative = relative_path(root, &path);
+        if is_dir {
+            if !filter.skips_dir(&relative) {
+                walk_dir(root, &path, filter, ignores, walk);
+            }
+        } else if file_type.is_file() && filter.includes_file(&relative) {
+            walk.files.push(path);
+        }
+    }
+
+    ignores.truncate(inherited);
+}
+
+fn is_ignored(ignores: &[IgnoreFile], path: &Path, is_dir: bool) -> bool {
+    let mut ignored = false;
+    for ignore in ignores {
+        if !path.starts_with(&ignore.dir) {
+            continue;
+        }
+        let relative = relative_path(&ignore.dir, path);
+        if let Some(rule) = last_match(&ignore.rules, &relative, is_dir) {
+            ignored = !rule.negated;
+        }
+    }
+    ignored
+}
+
+// Whether `rules` leave `relative` ignored, gitignore style.
+fn is_ignored_by(rules: &[Rule], relative: &str, is_dir: bool) -> bool {
+    last_match(rules, relative, is_dir).is_some_and(|rule| !rule.negated)
+}
+
+fn last_match<'a>(rules: &'a [Rule], relative: &str, is_dir: bool) -> Option<&'a Rule> {
+    rules
+        .iter()
+        .rev()
+        .find(|rule| rule.matches(relative, is_dir))
+}
+
+// `path` relative to `dir`, with `/` separators like the patterns use.
+fn relative_path(dir: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(dir).unwrap_or(path);
+    relative.to_string_lossy().replace('\\', "/")
+}
+
+struct IgnoreFile {
+    dir: PathBuf,
+    rules: Vec<Rule>,
+}
+
+impl IgnoreFile {
+    fn read(dir: &Path, name: &str) -> Option<IgnoreFile> {
+        let contents = fs::read_to_string(dir.join(name)).ok()?;
+        Some(IgnoreFile {
+            dir: dir.to_path_buf(),
+            // A broken pattern in someone's ignore file shouldn't stop the
+            // search.
+            rules: contents
+                .lines()
+                .filter_map(|line| Rule::parse(line).ok().flatten())
+                .collect(),
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Rule {
+    glob: Glob,
+    negated: bool,
+    dir_only: bool,
+    // Anchored rules match the whole relative path, the others only the
+    // file name.
+    anchored: bool,
+}
+
+impl Rule {
+    // None for blank lines and comments.
+    fn parse(line: &str) -> Result<Option<Rule>, GlobError> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+        Ok(Some(Rule {
+            glob: Glob::new(line)?,
+            negated,
+            dir_only,
+            anchored,
+        }))
+    }
+
+    fn matches(&self, relative: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        if self.anchored {
+            self.glob.is_match(relative)
+        } else {
+            let name = relative.rsplit('/').next().unwrap_or(relative);
+            self.glob.is_match(name)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn honors_ignore_files() {
+        let root = env::temp_dir().join(format!("minigrep-walk-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for dir in ["src/nested", "target/debug", "logs", ".git"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        let files = [
+            (".gitignore", "target/\n*.log\n!keep.log\n/TODO\n"),
+            ("src/.gitignore", "nested/*.tmp\n"),
+            ("src/lib.rs", ""),
+            ("src/TODO", ""),
+            ("src/nested/a.tmp", ""),
+            ("src/nested/b.rs", ""),
+            ("target/debug/minigrep", ""),
+            ("logs/app.log", ""),
+            ("logs/keep.log", ""),
+            (".git/HEAD", ""),
+            ("TODO", ""),
+        ];
+        for (path, contents) in files {
+            fs::write(root.join(path), contents).unwrap();
+        }
+
+        let found: Vec<String> = walk(&root)
+            .files
+            .iter()
+            .map(|path| {
+                path.strip_prefix(&root)
+                    .unwrap()
+                    .to_string_lossy()
+                    .replace('\\', "/")
+            })
+            .collect();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(
+            vec![
+                ".gitignore",
+                "logs/keep.log",
+                "src/.gitignore",
+                "src/TODO",
+                "src/lib.rs",
+                "src/nested/b.rs",
+            ],
+            found
+        );
+    }
+
+    #[test]
+    fn filters() {
+        let root = env::temp_dir().join(format!("minigrep-filter-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for dir in ["src/bin", "target/debug", "docs"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for path in [
+            "Cargo.toml",
+            "src/lib.rs",
+            "src/bin/gen.rs",
+            "src/notes.md",
+            "target/out.rs",
+            "target/debug/build.rs",
+            "docs/Cargo.toml",
+            "docs/guide.md",
+        ] {
+            fs::write(root.join(path), "").unwrap();
+        }
+        let found = |filter: &Filter| -> Vec<String> {
+            walk_filtered(&root, filter)
+                .files
+                .iter()
+                .map(|path| relative_path(&root, path))
+                .collect()
+        };
+
+        let mut filter = Filter::default();
+        for rule in ["target/", "bin/*", "!gen.rs"] {
+            filter.ignore(rule).unwrap();
+        }
+        filter.include("*.rs").unwrap();
+        filter.include("Cargo.*").unwrap();
+        assert_eq!(
+            vec![
+                "Cargo.toml",
+                "docs/Cargo.toml",
+                "src/bin/gen.rs",
+                "src/lib.rs"
+            ],
+            found(&filter)
+        );
+
+        // With -g globs the last match wins, but a `!` one that matches a
+        // directory skips all of it.
+        let mut filter = Filter::default();
+        for glob in ["*.rs", "*.toml", "!target/**", "target/out.rs", "!/docs"] {
+            filter.glob(glob).unwrap();
+        }
+        filter.exclude("*.toml").unwrap();
+        assert_eq!(
+            vec!["src/bin/gen.rs", "src/lib.rs", "target/out.rs"],
+            found(&filter)
+        );
+
+        let mut filter = Filter::default();
+        filter.glob("!*.rs").unwrap();
+        assert_eq!(
+            vec![
+                "Cargo.toml",
+                "docs/Cargo.toml",
+                "docs/guide.md",
+                "src/notes.md"
+            ],
+            found(&filter)
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+        assert!(Filter::default().include("[z-a]").is_err());
+    }
+
+    #[test]
+    fn unreadable_entries() {
+        let root = env::temp_dir().join(format!("minigrep-unreadable-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for dir in ["a", "b/locked"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        fs::write(root.join("a/one.txt"), "").unwrap();
+        fs::write(root.join("b/locked/hidden.txt"), "").unwrap();
+        fs::write(root.join("c.txt"), "").unwrap();
+
+        // A directory that's gone by the time it's read.
+        let mut found = Walk::default();
+        walk_dir(
+            &root,
+            &root.join("gone"),
+            &Filter::default(),
+            &mut Vec::new(),
+            &mut found,
+        );
+        assert!(found.files.is_empty());
+        assert_eq!(root.join("gone"), found.errors[0].0);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let locked = root.join("b/locked");
+            fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+            // Permissions don't apply to root.
+            if fs::read_dir(&locked).is_err() {
+                let found = walk(&root);
+                assert_eq!(
+                    vec![root.join("a/one.txt"), root.join("c.txt")],
+                    found.files
+                );
+                assert_eq!(1, found.errors.len());
+                assert_eq!(locked, found.errors[0].0);
+            }
+            fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn binary_detection() {
+        assert!(!is_binary(b"Rust:\nsafe, fast, productive.\n"));
+        assert!(is_binary(b"\x7fELF\x02\x01\x01\x00"));
+    }
+}
@@ -0,0 +1,324 @@
+// Runs the minigrep binary and checks its exit status and what it prints,
+// which is what scripts rely on: 0 if a line was selected, 1 if none was,
+// 2 on errors.
+
+use std::fs;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+// The binary, run from the crate's directory, unaffected by the
+// environment of whoever runs the tests.
+fn command() -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_minigrep"));
+    command
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .env_remove("IGNORE_CASE")
+        .env("MINIGREP_CONFIG", "")
+        .stdin(Stdio::null());
+    command
+}
+
+fn minigrep(args: &[&str]) -> Output {
+    command().args(args).output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+// A fresh directory for one test's files.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("minigrep-cli-{}-{name}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn match_and_no_match() {
+    let output = minigrep(&["frog", "data/poem.txt"]);
+    assert_eq!(Some(0), output.status.code());
+    assert_eq!("How public, like a frog\n", stdout(&output));
+
+    let output = minigrep(&["toad", "data/poem.txt"]);
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!("", stdout(&output));
+    assert_eq!("", stderr(&output));
+
+    // -c prints a count either way; the status still says whether anything
+    // was selected.
+    let output = minigrep(&["-c", "toad", "data/poem.txt"]);
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!("0\n", stdout(&output));
+}
+
+#[test]
+fn usage_errors() {
+    let output = minigrep(&["--no-such-option", "frog"]);
+    assert_eq!(Some(2), output.status.code());
+    assert!(stderr(&output).contains("Unknown option '--no-such-option'"));
+
+    let output = minigrep(&[]);
+    assert_eq!(Some(2), output.status.code());
+
+    let output = minigrep(&["--help"]);
+    assert_eq!(Some(0), output.status.code());
+    assert!(stdout(&output).starts_with("Usage: minigrep"));
+}
+
+#[test]
+fn invalid_pattern() {
+    let output = minigrep(&["-E", "(frog", "data/poem.txt"]);
+    assert_eq!(Some(2), output.status.code());
+    assert!(stderr(&output).contains("invalid regex"));
+    assert_eq!("", stdout(&output));
+}
+
+#[test]
+fn unreadable_input_is_reported_and_skipped() {
+    let output = minigrep(&["frog", "data/missing.txt", "data/poem.txt"]);
+    // The other file is still searched, but the run counts as failed.
+    assert_eq!(Some(2), output.status.code());
+    assert_eq!("data/poem.txt:How public, like a frog\n", stdout(&output));
+    let stderr = stderr(&output);
+    assert!(
+        stderr.starts_with("minigrep: data/missing.txt: "),
+        "{stderr}"
+    );
+
+    let output = minigrep(&["-j4", "toad", "data/missing.txt", "data/poem.txt"]);
+    assert_eq!(Some(2), output.status.code());
+}
+
+#[test]
+fn in_place_needs_utf8() {
+    let dir = temp_dir("encoding");
+    let path = dir.join("latin1.txt");
+    fs::write(&path, b"caf\xe9 frog\n").unwrap();
+
+    let output = minigrep(&[
+        "--replace=toad",
+        "--in-place",
+        "frog",
+        path.to_str().unwrap(),
+    ]);
+    assert_eq!(Some(2), output.status.code());
+    assert!(stderr(&output).contains("not valid UTF-8"));
+    assert_eq!(b"caf\xe9 frog\n", &fs::read(&path).unwrap()[..]);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn closed_output_is_not_an_error() {
+    let dir = temp_dir("pipe");
+    let path = dir.join("frogs.txt");
+    // Far more than a pipe holds, so minigrep is still writing when the
+    // reader goes away.
+    fs::write(&path, "How public, like a frog\n".repeat(200_000)).unwrap();
+
+    let mut child = command()
+        .args(["frog", path.to_str().unwrap()])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut first = [0; 24];
+    child.stdout.take().unwrap().read_exact(&mut first).unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert_eq!(b"How public, like a frog\n", &first);
+    assert_eq!(Some(0), output.status.code());
+    assert_eq!("", stderr(&output));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn config_file() {
+    let dir = temp_dir("config");
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::write(dir.join("src/lib.rs"), "// a frog\n").unwrap();
+    fs::write(dir.join("src/frog.min.js"), "frog\n").unwrap();
+    fs::write(dir.join("notes.md"), "Frog notes\n").unwrap();
+    let rc = dir.join("minigreprc");
+    fs::write(
+        &rc,
+        "-i\n--line-number\n[types]\nrust = *.rs\ndocs = *.md\n[ignore]\n*.min.js\n",
+    )
+    .unwrap();
+    let search = |args: &[&str]| {
+        let output = command()
+            .args(args)
+            .current_dir(&dir)
+            .env("MINIGREP_CONFIG", &rc)
+            .output()
+            .unwrap();
+        (output.status.code(), stdout(&output))
+    };
+
+    assert_eq!(
+        (
+            Some(0),
+            "./notes.md:1:Frog notes\n./src/lib.rs:1:// a frog\n".to_string()
+        ),
+        search(&["frog", "."])
+    );
+    assert_eq!(
+        (Some(0), "./src/lib.rs:1:// a frog\n".to_string()),
+        search(&["-t", "rust", "frog", "."])
+    );
+    // Without the file: case matters, nothing is ignored and there are no
+    // types.
+    assert_eq!(
+        (
+            Some(0),
+            "./src/frog.min.js:frog\n./src/lib.rs:// a frog\n".to_string()
+        ),
+        search(&["--no-config", "frog", "."])
+    );
+    // The command line can turn off what the file turns on.
+    assert_eq!(
+        (Some(0), "./src/lib.rs:// a frog\n".to_string()),
+        search(&["--no-line-number", "--no-ignore-case", "frog", "."])
+    );
+    // "rust" is built in, "docs" only comes from the file.
+    assert_eq!(
+        (Some(0), "./src/lib.rs:// a frog\n".to_string()),
+        search(&["--no-config", "-t", "rust", "frog", "."])
+    );
+    assert_eq!(
+        (Some(2), String::new()),
+        search(&["--no-config", "-t", "docs", "frog", "."])
+    );
+    assert_eq!(
+        (Some(0), "./src/frog.min.js:frog\n".to_string()),
+        search(&[
+            "--no-config",
+            "-g",
+            "!*.rs",
+            "-Tmarkdown",
+            "-i",
+            "frog",
+            "."
+        ])
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn multiline() {
+    let output = minigrep(&["-Un", "a frog\\nTo tell", "data/poem.txt"]);
+    assert_eq!(Some(0), output.status.code());
+    assert_eq!(
+        "7:How public, like a frog\n8:To tell your name the livelong day\n",
+        stdout(&output)
+    );
+
+    // Without -U, no line holds a line break.
+    let output = minigrep(&["-n", "a frog\\nTo tell", "data/poem.txt"]);
+    assert_eq!(Some(1), output.status.code());
+}
+
+#[test]
+fn counts_and_stats() {
+    let output = minigrep(&["--count-matches", "o", "data/poem.txt"]);
+    assert_eq!(Some(0), output.status.code());
+    assert_eq!("24\n", stdout(&output));
+    let output = minigrep(&["-c", "o", "data/poem.txt"]);
+    assert_eq!("8\n", stdout(&output));
+
+    let output = minigrep(&["--stats", "o", "data/poem.txt", "data/poem.txt.gz"]);
+    assert_eq!(Some(0), output.status.code());
+    let stdout = stdout(&output);
+    // The gzip file counts with the size of the text in it.
+    assert!(
+        stdout.contains(
+            "\n48 matches\n16 matched lines\n2 files contained matches\n2 files searched\n\
+             444 bytes searched\n"
+        ),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn fuzzy_ranks_every_input_together() {
+    let dir = temp_dir("fuzzy");
+    let (a, b) = (dir.join("a.log"), dir.join("b.log"));
+    fs::write(&a, "recieve\nreceive\n").unwrap();
+    fs::write(&b, "receive\nrecive\n").unwrap();
+    let (a, b) = (a.to_str().unwrap(), b.to_str().unwrap());
+
+    for threads in ["-j1", "-j4"] {
+        let output = minigrep(&[threads, "--fuzzy=2", "receive", a, b]);
+        assert_eq!(Some(0), output.status.code());
+        assert_eq!(
+            format!("{a}:~0:receive\n{b}:~0:receive\n{b}:~1:recive\n{a}:~2:recieve\n"),
+            stdout(&output)
+        );
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn follow() {
+    let dir = temp_dir("follow");
+    let path = dir.join("app.log");
+    fs::write(&path, "INFO up\nERROR one\n").unwrap();
+
+    let mut child = command()
+        .args(["--follow", "-n", "ERROR", path.to_str().unwrap()])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = child.stdout.take().unwrap();
+    let mut read = |len: usize| {
+        let mut buf = vec![0; len];
+        stdout.read_exact(&mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    };
+    assert_eq!("2:ERROR one\n", read(12));
+
+    let mut log = fs::OpenOptions::new().append(true).open(&path).unwrap();
+    log.write_all(b"INFO ok\nERROR two\n").unwrap();
+    assert_eq!("4:ERROR two\n", read(12));
+    // Rotated: the new file is followed from its start.
+    fs::rename(&path, dir.join("app.log.1")).unwrap();
+    fs::write(&path, "ERROR three\n").unwrap();
+    assert_eq!("1:ERROR three\n", read(14));
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn follow_with_context() {
+    let dir = temp_dir("follow-context");
+    let path = dir.join("app.log");
+    fs::write(&path, "x\nERROR a\n").unwrap();
+
+    let mut child = command()
+        .args(["--follow", "-n", "-A1", "ERROR", path.to_str().unwrap()])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = child.stdout.take().unwrap();
+    let mut read = |len: usize| {
+        let mut buf = vec![0; len];
+        stdout.read_exact(&mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    };
+    assert_eq!("2:ERROR a\n", read(10));
+
+    // The context line comes in a later append.
+    let mut log = fs::OpenOptions::new().append(true).open(&path).unwrap();
+    log.write_all(b"after1\nafter2\nERROR b\n").unwrap();
+    assert_eq!("3-after1\n--\n5:ERROR b\n", read(22));
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,170 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+};
+
+mod request;
+mod response;
+mod router;
+mod static_files;
+
+pub use request::{ParseError, Request};
+pub use response::Response;
+pub use router::{Handler, Params, Router};
+pub use static_files::StaticFiles;
+
+pub struct ThreadPool {
+    workers: Vec<Worker>,
+    sender: Option<mpsc::Sender<Job>>,
+}
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+impl ThreadPool {
+    /// Create a new ThreadPool.
+    ///
+    /// The size is the number of threads in the pool.
+    ///
+    /// # Panics
+    ///
+    /// The `new` function will panic if the size is zero.
+    pub fn new(size: usize) -> ThreadPool {
+        assert!(size > 0);
+
+        let (sender, receiver) = mpsc::channel();
+
+        // Every worker takes jobs from the same receiver, one at a time.
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let mut workers = Vec::with_capacity(size);
+
+        for id in 0..size {
+            workers.push(Worker::new(id, Arc::clone(&receiver)));
+        }
+
+        ThreadPool {
+            workers,
+            sender: Some(sender),
+        }
+    }
+
+    /// Run `f` on the next worker that's free.
+    pub fn execute<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let job = Box::new(f);
+
+        self.sender.as_ref().unwrap().send(job).unwrap();
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        // Closing the channel tells the workers to stop once the jobs
+        // already sent are done.
+        drop(self.sender.take());
+
+        for worker in &mut self.workers {
+            println!("Shutting down worker {}", worker.id);
+
+            if let Some(thread) = worker.thread.take() {
+                // Jobs can't panic the thread, so there's nothing to report.
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+struct Worker {
+    id: usize,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Worker {
+    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>) -> Worker {
+        let thread = thread::spawn(move || loop {
+            // The lock is only held while waiting for a job, not while
+            // running it.
+            let message = receiver.lock().unwrap().recv();
+
+            match message {
+                Ok(job) => {
+                    // A job that panics (the panic is reported as usual)
+                    // mustn't take the worker down with it, or the pool
+                    // would shrink with every one.
+                    let _ = panic::catch_unwind(AssertUnwindSafe(job));
+                }
+                Err(_) => {
+                    println!("Worker {id} disconnected; shutting down.");
+                    break;
+                }
+            }
+        });
+
+        Worker {
+            id,
+            thread: Some(thread),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Barrier;
+
+    #[test]
+    fn jobs_run_at_the_same_time() {
+        // Each job waits for all the others, so this only finishes if the
+        // pool really runs four at once.
+        let pool = ThreadPool::new(4);
+        let barrier = Arc::new(Barrier::new(4));
+        let (done, finished) = mpsc::channel();
+        for _ in 0..4 {
+            let barrier = Arc::clone(&barrier);
+            let done = done.clone();
+            pool.execute(move || {
+                barrier.wait();
+                done.send(()).unwrap();
+            });
+        }
+        for _ in 0..4 {
+            finished.recv().unwrap();
+        }
+    }
+
+    #[test]
+    fn drop_finishes_queued_jobs() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let pool = ThreadPool::new(2);
+        for _ in 0..8 {
+            let count = Arc::clone(&count);
+            pool.execute(move || {
+                thread::sleep(std::time::Duration::from_millis(10));
+                count.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        drop(pool);
+        assert_eq!(8, count.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn panicking_jobs_leave_the_worker() {
+        let pool = ThreadPool::new(1);
+        let (done, finished) = mpsc::channel();
+        for _ in 0..3 {
+            pool.execute(|| panic!("job failed"));
+        }
+        pool.execute(move || done.send(()).unwrap());
+        finished.recv().unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_threads() {
+        ThreadPool::new(0);
+    }
+}
@@ -1,38 +1,181 @@
+use hello::{Request, Response, Router, StaticFiles, ThreadPool};
 use std::{
-    fs,
-    io::{prelude::*, BufReader},
+    env, fs,
+    io::{self, prelude::*, BufReader},
     net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 fn main() {
-    let listener = TcpListener::bind("127.0.0.1:7878").unwrap();
+    // The address can be given as the first argument, e.g. to use another
+    // port, and the directory to serve /static/ from as the second.
+    let mut args = env::args().skip(1);
+    let address = args.next().unwrap_or_else(|| "127.0.0.1:7878".to_string());
+    let root = args.next().unwrap_or_else(|| "static".to_string());
+    let listener = TcpListener::bind(address).unwrap();
+    let pool = ThreadPool::new(4);
+    let router = Arc::new(app(StaticFiles::new(root)));
+    // Connections accepted but not yet picked up by a worker.
+    let waiting = Arc::new(AtomicUsize::new(0));
 
     for stream in listener.incoming() {
-        let stream = stream.unwrap();
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                // Like running out of file descriptors; that can pass, so
+                // back off a little rather than spin.
+                eprintln!("Accept failed: {err}");
+                thread::sleep(Duration::from_millis(10));
+                continue;
+            }
+        };
+        let router = Arc::clone(&router);
+        let waiting = Arc::clone(&waiting);
+        waiting.fetch_add(1, Ordering::SeqCst);
 
-        handle_connection(stream);
+        pool.execute(move || {
+            waiting.fetch_sub(1, Ordering::SeqCst);
+            if let Err(err) = handle_connection(stream, &router, &waiting) {
+                eprintln!("Connection failed: {err}");
+            }
+        });
     }
 }
 
-fn handle_connection(mut stream: TcpStream) {
-    let buf_reader = BufReader::new(&mut stream);
-    let request_line = buf_reader.lines().next().unwrap().unwrap();
-
-    let (status_line, filename) = match &request_line[..] {
-        "GET / HTTP/1.1" => ("HTTP/1.1 200 OK", "hello.html"),
-        "GET /sleep HTTP/1.1" => {
+fn app(files: StaticFiles) -> Router {
+    Router::new()
+        .get("/", |_, _| page(200, "hello.html"))
+        .get("/sleep", |_, _| {
             thread::sleep(Duration::from_secs(5));
-            ("HTTP/1.1 200 OK", "hello.html")
+            page(200, "hello.html")
+        })
+        .get("/static/*path", move |request, params| {
+            files.serve(request, params.get("path").unwrap_or(""))
+        })
+        .not_found(|_, _| page(404, "404.html"))
+}
+
+// A response with the contents of `filename`.
+fn page(status: u16, filename: &str) -> Response {
+    match fs::read_to_string(filename) {
+        Ok(contents) => Response::new(status)
+            .with_header("Content-Type", "text/html; charset=utf-8")
+            .with_body(contents),
+        Err(err) => Response::new(500).with_body(format!("{filename}: {err}\n")),
+    }
+}
+
+// A connection holds a worker for as long as it's open, so it only stays
+// open for another request until it's been quiet for IDLE_TIMEOUT, or just
+// briefly if other connections are waiting for a worker. Every connection
+// is closed after MAX_CONNECTION_AGE or MAX_REQUESTS regardless.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(5);
+const IDLE_POLL: Duration = Duration::from_millis(100);
+const MAX_CONNECTION_AGE: Duration = Duration::from_secs(60);
+const MAX_REQUESTS: usize = 100;
+
+fn handle_connection(stream: TcpStream, router: &Router, waiting: &AtomicUsize) -> io::Result<()> {
+    let mut writer = &stream;
+    // Whatever's read past one request stays buffered for the next, so
+    // pipelined requests are answered in the order they came.
+    let mut buf_reader = BufReader::new(Deadline {
+        stream: &stream,
+        timeout: IDLE_TIMEOUT,
+        deadline: Instant::now() + MAX_CONNECTION_AGE,
+    });
+
+    let mut served = 0;
+    loop {
+        if !request_arrives(&mut buf_reader, waiting)? {
+            return Ok(());
+        }
+        let request = match Request::read_from(&mut buf_reader) {
+            Ok(request) => request,
+            Err(err) => {
+                // Answer what can be answered; a closed or broken
+                // connection, or one that's gone quiet, has no one left to
+                // tell.
+                if let Some(status) = err.status() {
+                    Response::new(status)
+                        .with_header("Connection", "close")
+                        .with_body(format!("{err}\n"))
+                        .write_to(&mut writer)?;
+                }
+                return Ok(());
+            }
+        };
+        served += 1;
+
+        let keep_alive = request.keep_alive()
+            && served < MAX_REQUESTS
+            && Instant::now() < buf_reader.get_ref().deadline;
+        let connection = if keep_alive { "keep-alive" } else { "close" };
+        router
+            .handle(&request)
+            .with_header("Connection", connection)
+            .write_to(&mut writer)?;
+        if !keep_alive {
+            return Ok(());
+        }
+    }
+}
+
+// Waits for the first bytes of the next request. False if the client
+// closes the connection or stays quiet for too long instead.
+fn request_arrives(
+    buf_reader: &mut BufReader<Deadline>,
+    waiting: &AtomicUsize,
+) -> io::Result<bool> {
+    if !buf_reader.buffer().is_empty() {
+        return Ok(true);
+    }
+
+    let started = Instant::now();
+    buf_reader.get_mut().timeout = IDLE_POLL;
+    let arrived = loop {
+        match buf_reader.fill_buf() {
+            Ok(buf) => break Ok(!buf.is_empty()),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                if waiting.load(Ordering::SeqCst) > 0
+                    || started.elapsed() >= IDLE_TIMEOUT
+                    || Instant::now() >= buf_reader.get_ref().deadline
+                {
+                    break Ok(false);
+                }
+            }
+            Err(err) => break Err(err),
         }
-        _ => ("HTTP/1.1 404 NOT FOUND", "404.html"),
     };
+    buf_reader.get_mut().timeout = IDLE_TIMEOUT;
+    arrived
+}
+
+// The connection, read with `timeout` for each read but never past
+// `deadline`, so a client trickling in bytes can't keep it forever.
+struct Deadline<'a> {
+    stream: &'a TcpStream,
+    timeout: Duration,
+    deadline: Instant,
+}
 
-    let contents = fs::read_to_string(filename).unwrap();
-    let length = contents.len();
-    let response = format!(
-        "{status_line}\r\nContent-Length: {length}\r\n\r\n{contents}"
-    );
-    stream.write_all(response.as_bytes()).unwrap();
+impl Read for Deadline<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self.deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        self.stream.set_read_timeout(Some(self.timeout.min(left)))?;
+        self.stream.read(buf)
+    }
 }
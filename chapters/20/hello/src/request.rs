@@ -0,0 +1,451 @@
+// Parsing HTTP/1.x requests (RFC 9112) off a connection: the request line,
+// the headers, and a body sized by Content-Length or sent in chunks.
+//
+// Everything that's read is capped, so a client can't make the server hold
+// on to arbitrary amounts of memory; going over a limit is an error like any
+// other malformed input.
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    io::{self, BufRead, Read},
+};
+
+// The longest request line, and the longest header line.
+const MAX_LINE_LEN: usize = 8 * 1024;
+const MAX_HEADERS: usize = 100;
+// All the header lines together.
+const MAX_HEADERS_LEN: usize = 64 * 1024;
+const MAX_BODY_LEN: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    /// The part of the target before any `?`, as sent.
+    pub path: String,
+    /// The part after the `?`, without it.
+    pub query: Option<String>,
+    pub version: String,
+    /// Header names are lowercased. A header sent more than once has its
+    /// values joined with ", ".
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+    /// The connection closed before a request started, which is how a
+    /// client says it's done.
+    Closed,
+    Io(io::Error),
+    /// Not a request we can make sense of.
+    Malformed(String),
+    /// Request line or headers over the limits.
+    HeadersTooLarge,
+    BodyTooLarge,
+}
+
+impl ParseError {
+    /// The status to answer with, if there's anyone left to answer.
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            ParseError::Closed | ParseError::Io(_) => None,
+            ParseError::Malformed(_) => Some(400),
+            ParseError::HeadersTooLarge => Some(431),
+            ParseError::BodyTooLarge => Some(413),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Closed => write!(f, "connection closed"),
+            ParseError::Io(err) => write!(f, "{err}"),
+            ParseError::Malformed(reason) => write!(f, "malformed request: {reason}"),
+            ParseError::HeadersTooLarge => write!(f, "request headers too large"),
+            ParseError::BodyTooLarge => write!(f, "request body too large"),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+impl From<io::Error> for ParseError {
+    fn from(err: io::Error) -> ParseError {
+        ParseError::Io(err)
+    }
+}
+
+fn malformed(reason: impl Into<String>) -> ParseError {
+    ParseError::Malformed(reason.into())
+}
+
+impl Request {
+    /// Reads one request from `reader`, leaving anything after it (the next
+    /// request on the connection) unread.
+    pub fn read_from(reader: &mut impl BufRead) -> Result<Request, ParseError> {
+        let request_line = match read_line(reader, MAX_LINE_LEN)? {
+            Some(line) => line,
+            None => return Err(ParseError::Closed),
+        };
+        let mut parts = request_line.split(' ');
+        let (Some(method), Some(target), Some(version), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(malformed("expected 'METHOD TARGET VERSION'"));
+        };
+        if method.is_empty() || !method.bytes().all(is_token_byte) {
+            return Err(malformed(format!("bad method '{method}'")));
+        }
+        if !target.starts_with('/') {
+            return Err(malformed(format!("bad target '{target}'")));
+        }
+        if version != "HTTP/1.1" && version != "HTTP/1.0" {
+            return Err(malformed(format!("unsupported version '{version}'")));
+        }
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path, Some(query.to_string())),
+            None => (target, None),
+        };
+
+        let mut request = Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            query,
+            version: version.to_string(),
+            headers: HashMap::new(),
+            body: Vec::new(),
+        };
+        read_headers(reader, &mut request.headers)?;
+        request.body = read_body(reader, &request.headers)?;
+        Ok(request)
+    }
+
+    /// The value of the header called `name`, in any case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(&name.to_ascii_lowercase())
+            .map(String::as_str)
+    }
+
+    /// Whether the client wants the connection kept open for another
+    /// request: by default in HTTP/1.1, only when asked for in HTTP/1.0.
+    pub fn keep_alive(&self) -> bool {
+        let has_option = |option: &str| {
+            self.header("Connection").is_some_and(|options| {
+                options
+                    .split(',')
+                    .any(|other| other.trim().eq_ignore_ascii_case(option))
+            })
+        };
+        if self.version == "HTTP/1.0" {
+            has_option("keep-alive")
+        } else {
+            !has_option("close")
+        }
+    }
+}
+
+// Header lines up to the empty line that ends them.
+fn read_headers(
+    reader: &mut impl BufRead,
+    headers: &mut HashMap<String, String>,
+) -> Result<(), ParseError> {
+    let mut count = 0;
+    let mut total = 0;
+    loop {
+        let line = read_line(reader, MAX_LINE_LEN)?
+            .ok_or_else(|| malformed("connection closed in the headers"))?;
+        if line.is_empty() {
+            return Ok(());
+        }
+        count += 1;
+        total += line.len();
+        if count > MAX_HEADERS || total > MAX_HEADERS_LEN {
+            return Err(ParseError::HeadersTooLarge);
+        }
+
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| malformed(format!("bad header line '{line}'")))?;
+        // No whitespace allowed between the name and the colon.
+        if name.is_empty() || !name.bytes().all(is_token_byte) {
+            return Err(malformed(format!("bad header name '{name}'")));
+        }
+        let value = value.trim_matches(|c| c == ' ' || c == '\t');
+        headers
+            .entry(name.to_ascii_lowercase())
+            .and_modify(|values| {
+                values.push_str(", ");
+                values.push_str(value);
+            })
+            .or_insert_with(|| value.to_string());
+    }
+}
+
+fn read_body(
+    reader: &mut impl BufRead,
+    headers: &HashMap<String, String>,
+) -> Result<Vec<u8>, ParseError> {
+    let length = headers.get("content-length");
+    if let Some(encoding) = headers.get("transfer-encoding") {
+        // Both at once is a classic way to smuggle a second request past
+        // a proxy.
+        if length.is_some() {
+            return Err(malformed("both Content-Length and Transfer-Encoding"));
+        }
+        if !encoding.eq_ignore_ascii_case("chunked") {
+            return Err(malformed(format!(
+                "unsupported transfer encoding '{encoding}'"
+            )));
+        }
+        return read_chunked(reader);
+    }
+
+    let Some(length) = length else {
+        return Ok(Vec::new());
+    };
+    // A repeated header was joined with ", ", and all copies must agree.
+    let mut lengths = length.split(',').map(str::trim);
+    let first = lengths.next().unwrap_or_default();
+    if !lengths.all(|other| other == first) {
+        return Err(malformed("conflicting Content-Length headers"));
+    }
+    if first.is_empty() || !first.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(malformed(format!("bad Content-Length '{first}'")));
+    }
+    let length: usize = first.parse().map_err(|_| ParseError::BodyTooLarge)?;
+    if length > MAX_BODY_LEN {
+        return Err(ParseError::BodyTooLarge);
+    }
+    read_exactly(reader, length)
+}
+
+// A chunked body: chunks each with their size in hex, up to an empty one,
+// then optional trailer lines (which are dropped) and an empty line.
+fn read_chunked(reader: &mut impl BufRead) -> Result<Vec<u8>, ParseError> {
+    let mut body = Vec::new();
+    loop {
+        let line = read_line(reader, MAX_LINE_LEN)?
+            .ok_or_else(|| malformed("connection closed in a chunked body"))?;
+        // Chunk extensions after a ';' are allowed and ignored.
+        let size = line.split(';').next().unwrap_or_default().trim();
+        if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(malformed(format!("bad chunk size '{size}'")));
+        }
+        let size = usize::from_str_radix(size, 16).map_err(|_| ParseError::BodyTooLarge)?;
+        if size == 0 {
+            break;
+        }
+        // Written so that a size near usize::MAX can't overflow.
+        if size > MAX_BODY_LEN - body.len() {
+            return Err(ParseError::BodyTooLarge);
+        }
+        body.extend(read_exactly(reader, size)?);
+        if read_line(reader, MAX_LINE_LEN)?.is_none_or(|rest| !rest.is_empty()) {
+            return Err(malformed("chunk longer than its size"));
+        }
+    }
+
+    let mut trailers = HashMap::new();
+    read_headers(reader, &mut trailers)?;
+    Ok(body)
+}
+
+fn read_exactly(reader: &mut impl BufRead, len: usize) -> Result<Vec<u8>, ParseError> {
+    let mut body = Vec::with_capacity(len);
+    reader.take(len as u64).read_to_end(&mut body)?;
+    if body.len() < len {
+        return Err(malformed("connection closed in the body"));
+    }
+    Ok(body)
+}
+
+// One line, without its "\r\n" (or bare "\n"). None if the input ended
+// before anything was read.
+fn read_line(reader: &mut impl BufRead, limit: usize) -> Result<Option<String>, ParseError> {
+    let mut line = Vec::new();
+    loop {
+        let available = match reader.fill_buf() {
+            Ok(available) => available,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        };
+        if available.is_empty() {
+            if line.is_empty() {
+                return Ok(None);
+            }
+            return Err(malformed("connection closed mid-line"));
+        }
+        let (used, done) = match available.iter().position(|&b| b == b'\n') {
+            Some(i) => (i + 1, true),
+            None => (available.len(), false),
+        };
+        line.extend_from_slice(&available[..used]);
+        reader.consume(used);
+        // The limit doesn't count the line break.
+        if line.len() > limit + 2 {
+            return Err(ParseError::HeadersTooLarge);
+        }
+        if done {
+            break;
+        }
+    }
+
+    line.pop();
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|_| malformed("not UTF-8"))
+}
+
+// The characters HTTP allows in methods and header names.
+fn is_token_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Result<Request, ParseError> {
+        Request::read_from(&mut input.as_bytes())
+    }
+
+    #[test]
+    fn request_line_and_headers() {
+        let request = parse(
+            "GET /users/7?sort=name&x HTTP/1.1\r\n\
+             Host: localhost:7878\r\n\
+             Accept:  text/html \r\n\
+             accept: */*\r\n\
+             \r\n",
+        )
+        .unwrap();
+        assert_eq!("GET", request.method);
+        assert_eq!("/users/7", request.path);
+        assert_eq!(Some("sort=name&x"), request.query.as_deref());
+        assert_eq!("HTTP/1.1", request.version);
+        assert_eq!(Some("localhost:7878"), request.header("HOST"));
+        assert_eq!(Some("text/html, */*"), request.header("accept"));
+        assert!(request.body.is_empty());
+
+        // Bare "\n" line endings are tolerated.
+        let request = parse("GET / HTTP/1.0\nHost: x\n\n").unwrap();
+        assert_eq!(("/", None), (request.path.as_str(), request.query));
+    }
+
+    #[test]
+    fn keep_alive() {
+        for (request, keep_alive) in [
+            ("GET / HTTP/1.1\r\n\r\n", true),
+            ("GET / HTTP/1.1\r\nConnection: Close\r\n\r\n", false),
+            (
+                "GET / HTTP/1.1\r\nConnection: upgrade, close\r\n\r\n",
+                false,
+            ),
+            ("GET / HTTP/1.0\r\n\r\n", false),
+            ("GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\n", true),
+        ] {
+            assert_eq!(
+                keep_alive,
+                parse(request).unwrap().keep_alive(),
+                "{request}"
+            );
+        }
+    }
+
+    #[test]
+    fn bodies() {
+        let mut input =
+            "POST /echo HTTP/1.1\r\nContent-Length: 5\r\n\r\nhelloGET / HTTP/1.1\r\n\r\n"
+                .as_bytes();
+        let request = Request::read_from(&mut input).unwrap();
+        assert_eq!(b"hello", &request.body[..]);
+        // The next request is left for the next read.
+        assert_eq!("/", Request::read_from(&mut input).unwrap().path);
+        assert!(matches!(
+            Request::read_from(&mut input),
+            Err(ParseError::Closed)
+        ));
+
+        let request = parse(
+            "POST /echo HTTP/1.1\r\n\
+             Transfer-Encoding: chunked\r\n\
+             \r\n\
+             5;name=value\r\nhello\r\n\
+             7\r\n, world\r\n\
+             0\r\n\
+             Expires: never\r\n\
+             \r\n",
+        )
+        .unwrap();
+        assert_eq!(b"hello, world", &request.body[..]);
+    }
+
+    #[test]
+    fn malformed_requests() {
+        for input in [
+            "GET /\r\n\r\n",
+            "GET  / HTTP/1.1\r\n\r\n",
+            "GET / HTTP/2\r\n\r\n",
+            "GET index.html HTTP/1.1\r\n\r\n",
+            "G(T / HTTP/1.1\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost localhost\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost : localhost\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost: localhost\r\n",
+            "GET / HTTP/1.1\r\nContent-Length: -1\r\n\r\n",
+            "GET / HTTP/1.1\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\nab",
+            "POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nshort",
+            "POST / HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\n",
+            "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 3\r\n\r\n",
+            "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n",
+            "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabcd\r\n0\r\n\r\n",
+        ] {
+            let result = parse(input);
+            assert!(
+                matches!(result, Err(ParseError::Malformed(_))),
+                "{input:?}: {result:?}"
+            );
+        }
+        let invalid_utf8 = Request::read_from(&mut &b"GET /\xff HTTP/1.1\r\n\r\n"[..]);
+        assert!(matches!(invalid_utf8, Err(ParseError::Malformed(_))));
+    }
+
+    #[test]
+    fn limits() {
+        let long_line = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_LINE_LEN));
+        assert!(matches!(
+            parse(&long_line),
+            Err(ParseError::HeadersTooLarge)
+        ));
+
+        let many_headers = format!(
+            "GET / HTTP/1.1\r\n{}\r\n",
+            "X-A: b\r\n".repeat(MAX_HEADERS + 1)
+        );
+        assert!(matches!(
+            parse(&many_headers),
+            Err(ParseError::HeadersTooLarge)
+        ));
+
+        let big_body = format!(
+            "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY_LEN + 1
+        );
+        assert!(matches!(parse(&big_body), Err(ParseError::BodyTooLarge)));
+        let huge_chunk =
+            "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nfffffffffffffffffff\r\n";
+        assert!(matches!(parse(huge_chunk), Err(ParseError::BodyTooLarge)));
+        let max_chunk = "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+                         1\r\na\r\nffffffffffffffff\r\n";
+        assert!(matches!(parse(max_chunk), Err(ParseError::BodyTooLarge)));
+
+        assert_eq!(Some(413), ParseError::BodyTooLarge.status());
+        assert_eq!(None, ParseError::Closed.status());
+    }
+}
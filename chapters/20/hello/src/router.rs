@@ -0,0 +1,259 @@
+// Dispatching requests to handlers by method and path.
+//
+// Path patterns are split on '/'. A segment like `:id` matches any one
+// segment and captures it as the parameter `id`; a last segment like
+// `*path` captures everything from there on (possibly nothing). Routes are
+// tried in the order they were added, and the first one that matches wins.
+
+use crate::{Request, Response};
+
+pub type Handler = Box<dyn Fn(&Request, &Params) -> Response + Send + Sync>;
+
+/// The parameters a route's pattern captured, as they appear in the path.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Params(Vec<(String, String)>);
+
+impl Params {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(other, _)| other == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Debug)]
+enum Segment {
+    Literal(String),
+    Param(String),
+    Rest(String),
+}
+
+struct Route {
+    method: String,
+    pattern: Vec<Segment>,
+    handler: Handler,
+}
+
+pub struct Router {
+    routes: Vec<Route>,
+    // For paths that no route matches.
+    not_found: Handler,
+}
+
+impl Default for Router {
+    fn default() -> Router {
+        Router::new()
+    }
+}
+
+impl Router {
+    pub fn new() -> Router {
+        Router {
+            routes: Vec::new(),
+            not_found: Box::new(|_, _| Response::new(404).with_body("Not Found\n")),
+        }
+    }
+
+    /// Adds a route for `method` requests to paths matching `pattern`.
+    ///
+    /// # Panics
+    ///
+    /// If `pattern` doesn't start with '/', or has a `*` segment that
+    /// isn't the last one.
+    pub fn route<F>(mut self, method: &str, pattern: &str, handler: F) -> Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        assert!(pattern.starts_with('/'), "pattern must start with '/'");
+        let segments: Vec<&str> = pattern[1..].split('/').collect();
+        let pattern = segments
+            .iter()
+            .enumerate()
+            .map(|(i, segment)| {
+                if let Some(name) = segment.strip_prefix('*') {
+                    assert!(i + 1 == segments.len(), "'*{name}' must come last");
+                    Segment::Rest(name.to_string())
+                } else if let Some(name) = segment.strip_prefix(':') {
+                    Segment::Param(name.to_string())
+                } else {
+                    Segment::Literal(segment.to_string())
+                }
+            })
+            .collect();
+        self.routes.push(Route {
+            method: method.to_string(),
+            pattern,
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    pub fn get<F>(self, pattern: &str, handler: F) -> Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.route("GET", pattern, handler)
+    }
+
+    pub fn post<F>(self, pattern: &str, handler: F) -> Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.route("POST", pattern, handler)
+    }
+
+    /// Uses `handler` for paths no route matches, instead of a bare 404.
+    pub fn not_found<F>(mut self, handler: F) -> Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.not_found = Box::new(handler);
+        self
+    }
+
+    /// Runs the handler for `request`. A path that some route matches, but
+    /// not for this method, gets a 405 listing the methods that would do.
+    pub fn handle(&self, request: &Request) -> Response {
+        let mut allowed: Vec<&str> = Vec::new();
+        for route in &self.routes {
+            let Some(params) = match_path(&route.pattern, &request.path) else {
+                continue;
+            };
+            if route.method == request.method {
+                return (route.handler)(request, &params);
+            }
+            if !allowed.contains(&route.method.as_str()) {
+                allowed.push(&route.method);
+            }
+        }
+
+        if allowed.is_empty() {
+            return (self.not_found)(request, &Params::default());
+        }
+        allowed.sort_unstable();
+        Response::new(405)
+            .with_header("Allow", &allowed.join(", "))
+            .with_body("Method Not Allowed\n")
+    }
+}
+
+// The parameters `pattern` captures from `path`, if it matches.
+fn match_path(pattern: &[Segment], path: &str) -> Option<Params> {
+    let mut params = Vec::new();
+    // What's left after the next '/', or None once the path is used up.
+    let mut rest = path.strip_prefix('/');
+    for part in pattern {
+        let left = rest?;
+        if let Segment::Rest(name) = part {
+            params.push((name.clone(), left.to_string()));
+            return Some(Params(params));
+        }
+        let segment = match left.split_once('/') {
+            Some((segment, after)) => {
+                rest = Some(after);
+                segment
+            }
+            None => {
+                rest = None;
+                left
+            }
+        };
+        match part {
+            Segment::Literal(literal) if literal == segment => {}
+            Segment::Param(name) if !segment.is_empty() => {
+                params.push((name.clone(), segment.to_string()));
+            }
+            _ => return None,
+        }
+    }
+    // Every segment of the path has to be used up.
+    rest.is_none().then_some(Params(params))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn request(method: &str, path: &str) -> Request {
+        Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            query: None,
+            version: "HTTP/1.1".to_string(),
+            headers: HashMap::new(),
+            body: Vec::new(),
+        }
+    }
+
+    fn router() -> Router {
+        let echo = |name: &'static str| {
+            move |_: &Request, params: &Params| {
+                let mut body = name.to_string();
+                for key in ["id", "path"] {
+                    if let Some(value) = params.get(key) {
+                        body += &format!(" {key}={value}");
+                    }
+                }
+                Response::new(200).with_body(body)
+            }
+        };
+        Router::new()
+            .get("/", echo("index"))
+            .get("/users/:id", echo("user"))
+            .route("DELETE", "/users/:id", echo("delete"))
+            .get("/users/me", echo("never"))
+            .get("/static/*path", echo("static"))
+            .post("/echo", echo("echo"))
+    }
+
+    fn body(router: &Router, method: &str, path: &str) -> (u16, String) {
+        let response = router.handle(&request(method, path));
+        (response.status, String::from_utf8(response.body).unwrap())
+    }
+
+    #[test]
+    fn dispatch() {
+        let router = router();
+        assert_eq!((200, "index".to_string()), body(&router, "GET", "/"));
+        assert_eq!(
+            (200, "user id=7".to_string()),
+            body(&router, "GET", "/users/7")
+        );
+        // The first matching route wins.
+        assert_eq!(
+            (200, "user id=me".to_string()),
+            body(&router, "GET", "/users/me")
+        );
+        assert_eq!(
+            (200, "delete id=7".to_string()),
+            body(&router, "DELETE", "/users/7")
+        );
+        assert_eq!(
+            (200, "static path=css/site.css".to_string()),
+            body(&router, "GET", "/static/css/site.css")
+        );
+        assert_eq!(
+            (200, "static path=".to_string()),
+            body(&router, "GET", "/static/")
+        );
+        assert_eq!((200, "echo".to_string()), body(&router, "POST", "/echo"));
+    }
+
+    #[test]
+    fn not_found_and_not_allowed() {
+        let router = router();
+        for path in ["/users", "/users/", "/users/7/posts", "/nope", "/static"] {
+            assert_eq!(404, body(&router, "GET", path).0, "{path}");
+        }
+
+        let response = router.handle(&request("POST", "/users/7"));
+        assert_eq!(405, response.status);
+        assert_eq!(Some("DELETE, GET"), response.header("Allow"));
+
+        let router = router
+            .not_found(|request, _| Response::new(404).with_body(format!("no {}", request.path)));
+        assert_eq!((404, "no /nope".to_string()), body(&router, "GET", "/nope"));
+    }
+}
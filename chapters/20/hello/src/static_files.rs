@@ -0,0 +1,554 @@
+// Serving the files under a root directory: a directory's index.html,
+// content types from file extensions, conditional requests by ETag or
+// modification time (RFC 9110 section 13), and single byte ranges (section
+// 14).
+//
+// Paths are decoded and checked segment by segment, and anything that ends
+// up outside the root, like through a symlink, isn't served.
+
+use crate::{Request, Response};
+use std::{
+    fs::{self, File, Metadata},
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+pub struct StaticFiles {
+    root: PathBuf,
+}
+
+impl StaticFiles {
+    pub fn new(root: impl Into<PathBuf>) -> StaticFiles {
+        StaticFiles { root: root.into() }
+    }
+
+    /// Answers `request` with the file at `path`, relative to the root and
+    /// still percent-encoded, as a router's `*path` captures it.
+    pub fn serve(&self, request: &Request, path: &str) -> Response {
+        match self.respond(request, path) {
+            Ok(response) => response,
+            Err(err) => match err.kind() {
+                io::ErrorKind::NotFound => plain(404, "Not Found"),
+                io::ErrorKind::PermissionDenied => plain(403, "Forbidden"),
+                _ => plain(500, &err.to_string()),
+            },
+        }
+    }
+
+    fn respond(&self, request: &Request, path: &str) -> io::Result<Response> {
+        let Some(relative) = decode_path(path) else {
+            return Ok(plain(403, "Forbidden"));
+        };
+        let mut file = self.root.join(relative);
+        let mut metadata = fs::metadata(&file)?;
+        // Before giving anything away about it, even that it's a directory.
+        if !self.contains(&file)? {
+            return Ok(plain(404, "Not Found"));
+        }
+        if metadata.is_dir() {
+            // Links in the index are relative to the directory, so it has
+            // to be asked for with the slash.
+            if !request.path.ends_with('/') {
+                return Ok(plain(301, "Moved Permanently")
+                    .with_header("Location", &format!("{}/", request.path)));
+            }
+            file.push("index.html");
+            metadata = fs::metadata(&file)?;
+        }
+        if !metadata.is_file() || !self.contains(&file)? {
+            return Ok(plain(404, "Not Found"));
+        }
+
+        let len = metadata.len();
+        let etag = etag(&metadata);
+        let modified = metadata.modified().ok();
+        let mut response = Response::new(200)
+            .with_header("Content-Type", content_type(&file))
+            .with_header("Accept-Ranges", "bytes")
+            .with_header("ETag", &etag);
+        if let Some(modified) = modified {
+            response = response.with_header("Last-Modified", &http_date(modified));
+        }
+
+        if not_modified(request, &etag, modified) {
+            response.status = 304;
+            return Ok(response);
+        }
+        match range(request, len, &etag, modified) {
+            None => response.body = fs::read(&file)?,
+            Some(Some((start, end))) => {
+                let mut body = vec![0; (end - start + 1) as usize];
+                let mut file = File::open(&file)?;
+                file.seek(SeekFrom::Start(start))?;
+                file.read_exact(&mut body)?;
+                response.status = 206;
+                response.body = body;
+                response =
+                    response.with_header("Content-Range", &format!("bytes {start}-{end}/{len}"));
+            }
+            Some(None) => {
+                return Ok(plain(416, "Range Not Satisfiable")
+                    .with_header("Content-Range", &format!("bytes */{len}")));
+            }
+        }
+        Ok(response)
+    }
+
+    // Whether `file` is under the root once symlinks are followed.
+    fn contains(&self, file: &Path) -> io::Result<bool> {
+        Ok(file.canonicalize()?.starts_with(self.root.canonicalize()?))
+    }
+}
+
+// A response with just `text` for a body.
+fn plain(status: u16, text: &str) -> Response {
+    Response::new(status)
+        .with_header("Content-Type", "text/plain; charset=utf-8")
+        .with_body(format!("{text}\n"))
+}
+
+// The percent-decoded `path` as a relative path, or None if it tries to
+// leave the directory it's in.
+fn decode_path(path: &str) -> Option<PathBuf> {
+    let mut relative = PathBuf::new();
+    for segment in path.split('/') {
+        let segment = percent_decode(segment)?;
+        if segment.contains(['/', '\\', '\0']) || segment == ".." {
+            return None;
+        }
+        if !segment.is_empty() && segment != "." {
+            relative.push(segment);
+        }
+    }
+    Some(relative)
+}
+
+fn percent_decode(segment: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(segment.len());
+    let mut rest = segment.as_bytes();
+    while let Some((&byte, after)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(after.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &after[2..];
+        } else {
+            bytes.push(byte);
+            rest = after;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+fn content_type(file: &Path) -> &'static str {
+    let extension = file
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    match &extension[..] {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "txt" => "text/plain; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "xml" => "application/xml",
+        "json" => "application/json",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+// Changes whenever the file's length or modification time does.
+fn etag(metadata: &Metadata) -> String {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    format!("\"{:x}-{:x}\"", modified.as_nanos(), metadata.len())
+}
+
+// Whether the client's copy is still good. If-None-Match wins over
+// If-Modified-Since when both are sent.
+fn not_modified(request: &Request, etag: &str, modified: Option<SystemTime>) -> bool {
+    if let Some(tags) = request.header("If-None-Match") {
+        // Weak comparison: W/ doesn't matter here.
+        return tags.trim() == "*"
+            || tags
+                .split(',')
+                .any(|tag| tag.trim().trim_start_matches("W/") == etag);
+    }
+    match (
+        request
+            .header("If-Modified-Since")
+            .and_then(parse_http_date),
+        modified,
+    ) {
+        (Some(since), Some(modified)) => seconds(modified) <= seconds(since),
+        _ => false,
+    }
+}
+
+// The inclusive byte range the request asks for: None to send the whole
+// file, Some(None) if the range can't be satisfied.
+fn range(
+    request: &Request,
+    len: u64,
+    etag: &str,
+    modified: Option<SystemTime>,
+) -> Option<Option<(u64, u64)>> {
+    let spec = request.header("Range")?.trim().strip_prefix("bytes=")?;
+    // A range for an older version of the file is no use to the client.
+    if let Some(validator) = request.header("If-Range") {
+        let current = validator.trim() == etag
+            || modified.is_some_and(|modified| validator.trim() == http_date(modified));
+        if !current {
+            return None;
+        }
+    }
+    // Several ranges would need a multipart body; the whole file will do.
+    if spec.contains(',') {
+        return None;
+    }
+
+    let (first, last) = spec.trim().split_once('-')?;
+    let (start, end) = if first.is_empty() {
+        // The last `n` bytes.
+        let n: u64 = last.parse().ok()?;
+        if n == 0 {
+            return Some(None);
+        }
+        (len.saturating_sub(n), len.wrapping_sub(1))
+    } else {
+        let start: u64 = first.parse().ok()?;
+        let end = match last {
+            "" => u64::MAX,
+            last => last.parse().ok()?,
+        };
+        if end < start {
+            return None;
+        }
+        (start, end.min(len.wrapping_sub(1)))
+    };
+    if start >= len {
+        return Some(None);
+    }
+    Some(Some((start, end)))
+}
+
+fn seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+// `time` as an IMF-fixdate, like "Sun, 06 Nov 1994 08:49:37 GMT".
+fn http_date(time: SystemTime) -> String {
+    let secs = seconds(time);
+    let days = secs / 86400;
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{}, {day:02} {} {year} {:02}:{:02}:{:02} GMT",
+        DAYS[(days % 7) as usize],
+        MONTHS[month as usize - 1],
+        secs % 86400 / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+// The latest year a date is read for; IMF-fixdate years have four digits.
+const MAX_YEAR: u64 = 9999;
+
+// The other date formats are obsolete, and a date that can't be read, or
+// is out of range, is ignored.
+fn parse_http_date(date: &str) -> Option<SystemTime> {
+    let fields: Vec<&str> = date.split_whitespace().collect();
+    let [_, day, month, year, time, "GMT"] = fields[..] else {
+        return None;
+    };
+    let month = MONTHS.iter().position(|&name| name == month)? as u64 + 1;
+    let days = days_from_civil(year.parse().ok()?, month, day.parse().ok()?)?;
+    let mut hms = time.split(':').map(|field| field.parse::<u64>().ok());
+    let (Some(Some(hour)), Some(Some(minute)), Some(Some(second)), None) =
+        (hms.next(), hms.next(), hms.next(), hms.next())
+    else {
+        return None;
+    };
+    if hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    let secs = days
+        .checked_mul(86400)?
+        .checked_add(hour * 3600 + minute * 60 + second)?;
+    UNIX_EPOCH.checked_add(Duration::from_secs(secs))
+}
+
+// Howard Hinnant's conversions between days since 1970-01-01 and
+// (year, month, day), for the years after 1970.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days % 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+fn days_from_civil(year: u64, month: u64, day: u64) -> Option<u64> {
+    if !(1970..=MAX_YEAR).contains(&year) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let year = year - u64::from(month <= 2);
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let mp = (month + 9) % 12;
+    let day_of_year = (153 * mp + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    Some(era * 146097 + day_of_era - 719468)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::env;
+    use std::process;
+
+    // A fresh document root holding `files`.
+    fn root(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = env::temp_dir().join(format!("hello-static-{}-{name}", process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (path, contents) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        root
+    }
+
+    fn get(path: &str, headers: &[(&str, &str)]) -> Request {
+        Request {
+            method: "GET".to_string(),
+            path: path.to_string(),
+            query: None,
+            version: "HTTP/1.1".to_string(),
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_ascii_lowercase(), value.to_string()))
+                .collect::<HashMap<_, _>>(),
+            body: Vec::new(),
+        }
+    }
+
+    fn serve(files: &StaticFiles, path: &str, headers: &[(&str, &str)]) -> Response {
+        files.serve(&get(&format!("/static/{path}"), headers), path)
+    }
+
+    #[test]
+    fn files_and_indexes() {
+        let root = root(
+            "files",
+            &[
+                ("index.html", "<p>home</p>"),
+                ("css/site.css", "p {}"),
+                ("docs/index.html", "<p>docs</p>"),
+                ("a b.TXT", "spaced"),
+            ],
+        );
+        let files = StaticFiles::new(&root);
+
+        let response = serve(&files, "css/site.css", &[]);
+        assert_eq!(200, response.status);
+        assert_eq!(b"p {}", &response.body[..]);
+        assert_eq!(
+            Some("text/css; charset=utf-8"),
+            response.header("Content-Type")
+        );
+        assert_eq!(Some("bytes"), response.header("Accept-Ranges"));
+
+        assert_eq!(b"<p>home</p>", &serve(&files, "", &[]).body[..]);
+        assert_eq!(b"<p>docs</p>", &serve(&files, "docs/", &[]).body[..]);
+        let response = serve(&files, "docs", &[]);
+        assert_eq!(301, response.status);
+        assert_eq!(Some("/static/docs/"), response.header("Location"));
+
+        let response = serve(&files, "a%20b.TXT", &[]);
+        assert_eq!(b"spaced", &response.body[..]);
+        assert_eq!(
+            Some("text/plain; charset=utf-8"),
+            response.header("Content-Type")
+        );
+
+        assert_eq!(404, serve(&files, "nope.html", &[]).status);
+        assert_eq!(404, serve(&files, "css/", &[]).status);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn traversal() {
+        let root = root("traversal", &[("public/ok.txt", "ok")]);
+        fs::write(root.join("secret.txt"), "secret").unwrap();
+        let files = StaticFiles::new(root.join("public"));
+
+        assert_eq!(200, serve(&files, "./ok.txt", &[]).status);
+        for path in [
+            "../secret.txt",
+            "%2e%2e/secret.txt",
+            "x/../../secret.txt",
+            "..%2fsecret.txt",
+            "%",
+            "%zz",
+        ] {
+            assert_eq!(403, serve(&files, path, &[]).status, "{path}");
+        }
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(root.join("secret.txt"), root.join("public/link")).unwrap();
+            assert_eq!(404, serve(&files, "link", &[]).status);
+            // A linked directory isn't even redirected to.
+            fs::create_dir(root.join("private")).unwrap();
+            fs::write(root.join("private/index.html"), "private").unwrap();
+            std::os::unix::fs::symlink(root.join("private"), root.join("public/dir")).unwrap();
+            assert_eq!(404, serve(&files, "dir", &[]).status);
+            assert_eq!(404, serve(&files, "dir/", &[]).status);
+        }
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn conditional_requests() {
+        let root = root("conditional", &[("page.html", "page")]);
+        let files = StaticFiles::new(&root);
+
+        let response = serve(&files, "page.html", &[]);
+        let etag = response.header("ETag").unwrap().to_string();
+        let modified = response.header("Last-Modified").unwrap().to_string();
+
+        let response = serve(&files, "page.html", &[("If-None-Match", &etag)]);
+        assert_eq!(304, response.status);
+        assert!(response.body.is_empty());
+        assert_eq!(Some(&etag[..]), response.header("ETag"));
+        let tags = format!("\"other\", W/{etag}");
+        assert_eq!(
+            304,
+            serve(&files, "page.html", &[("If-None-Match", &tags)]).status
+        );
+        assert_eq!(
+            200,
+            serve(&files, "page.html", &[("If-None-Match", "\"other\"")]).status
+        );
+
+        assert_eq!(
+            304,
+            serve(&files, "page.html", &[("If-Modified-Since", &modified)]).status
+        );
+        let old = "Thu, 01 Jan 1970 00:00:00 GMT";
+        assert_eq!(
+            200,
+            serve(&files, "page.html", &[("If-Modified-Since", old)]).status
+        );
+        // If-None-Match wins.
+        let both = [
+            ("If-None-Match", "\"other\""),
+            ("If-Modified-Since", &modified),
+        ];
+        assert_eq!(200, serve(&files, "page.html", &both).status);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn ranges() {
+        let root = root("ranges", &[("digits.txt", "0123456789")]);
+        let files = StaticFiles::new(&root);
+        let range = |spec: &str| {
+            let response = serve(&files, "digits.txt", &[("Range", spec)]);
+            (
+                response.status,
+                response.header("Content-Range").map(str::to_string),
+                String::from_utf8(response.body).unwrap(),
+            )
+        };
+
+        let partial =
+            |range: &str, body: &str| (206, Some(format!("bytes {range}/10")), body.to_string());
+        assert_eq!(partial("2-4", "234"), range("bytes=2-4"));
+        assert_eq!(partial("7-9", "789"), range("bytes=7-"));
+        assert_eq!(partial("7-9", "789"), range("bytes=-3"));
+        assert_eq!(partial("0-9", "0123456789"), range("bytes=-30"));
+        assert_eq!(partial("8-9", "89"), range("bytes=8-100"));
+
+        let unsatisfiable = (
+            416,
+            Some("bytes */10".to_string()),
+            "Range Not Satisfiable\n".to_string(),
+        );
+        assert_eq!(unsatisfiable, range("bytes=10-"));
+        assert_eq!(unsatisfiable, range("bytes=-0"));
+
+        let whole = (200, None, "0123456789".to_string());
+        for spec in ["bytes=4-2", "bytes=0-1,4-5", "items=0-1", "bytes=x-"] {
+            assert_eq!(whole, range(spec), "{spec}");
+        }
+
+        let etag = serve(&files, "digits.txt", &[])
+            .header("ETag")
+            .unwrap()
+            .to_string();
+        let response = serve(
+            &files,
+            "digits.txt",
+            &[("Range", "bytes=0-0"), ("If-Range", &etag)],
+        );
+        assert_eq!(206, response.status);
+        let response = serve(
+            &files,
+            "digits.txt",
+            &[("Range", "bytes=0-0"), ("If-Range", "\"old\"")],
+        );
+        assert_eq!(200, response.status);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn dates() {
+        let time = UNIX_EPOCH + Duration::from_secs(784111777);
+        assert_eq!("Sun, 06 Nov 1994 08:49:37 GMT", http_date(time));
+        assert_eq!(Some(time), parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"));
+        let leap = UNIX_EPOCH + Duration::from_secs(951782400);
+        assert_eq!("Tue, 29 Feb 2000 00:00:00 GMT", http_date(leap));
+        assert_eq!(Some(leap), parse_http_date(&http_date(leap)));
+        assert_eq!(None, parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"));
+        assert_eq!(None, parse_http_date("Sun, 06 Nov 1994 08:49 GMT"));
+        for date in [
+            "Sun, 06 Nov 1994 9999999999999999:00:00 GMT",
+            "Sun, 06 Nov 1994 24:00:00 GMT",
+            "Sun, 06 Nov 1994 08:60:00 GMT",
+            "Sun, 06 Nov 1994 08:49:60 GMT",
+            "Sun, 06 Nov 99999999999999999 08:49:37 GMT",
+            "Sun, 06 Nov 10000 08:49:37 GMT",
+        ] {
+            assert_eq!(None, parse_http_date(date), "{date}");
+        }
+    }
+}
@@ -0,0 +1,289 @@
+// Runs the server binary on a free port and talks to it over TCP.
+
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::{self, Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// The running server, killed when the test is done with it.
+struct Server {
+    child: Child,
+    address: String,
+}
+
+impl Server {
+    fn start() -> Server {
+        Server::start_with(&[])
+    }
+
+    // With `args` after the address.
+    fn start_with(args: &[&str]) -> Server {
+        // Let the OS pick a port that's free right now.
+        let address = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .to_string();
+        let child = Command::new(env!("CARGO_BIN_EXE_hello"))
+            .arg(&address)
+            .args(args)
+            .current_dir(env!("CARGO_MANIFEST_DIR"))
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        // Wait for it to start listening.
+        let started = Instant::now();
+        let server = Server { child, address };
+        while server.try_get("/").is_err() {
+            assert!(
+                started.elapsed() < Duration::from_secs(10),
+                "server didn't start"
+            );
+            thread::sleep(Duration::from_millis(20));
+        }
+        server
+    }
+
+    fn get(&self, path: &str) -> String {
+        self.try_get(path).unwrap()
+    }
+
+    fn try_get(&self, path: &str) -> io::Result<String> {
+        let mut stream = TcpStream::connect(&self.address)?;
+        write!(
+            stream,
+            "GET {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n"
+        )?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        Ok(response)
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[test]
+fn sleep_does_not_block_other_requests() {
+    let server = Server::start();
+
+    thread::scope(|scope| {
+        let sleeping = scope.spawn(|| server.get("/sleep"));
+        // Give /sleep time to reach the server first.
+        thread::sleep(Duration::from_millis(200));
+
+        let started = Instant::now();
+        let response = server.get("/");
+        assert!(
+            started.elapsed() < Duration::from_secs(2),
+            "/ took {:?}",
+            started.elapsed()
+        );
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+        assert!(response.contains("<h1>Hello!</h1>"));
+
+        assert!(sleeping.join().unwrap().starts_with("HTTP/1.1 200 OK\r\n"));
+    });
+}
+
+#[test]
+fn unknown_path() {
+    let server = Server::start();
+    let response = server.get("/nope");
+    assert!(
+        response.starts_with("HTTP/1.1 404 Not Found\r\n"),
+        "{response}"
+    );
+    assert!(response.contains("<h1>Oops!</h1>"));
+}
+
+#[test]
+fn wrong_method() {
+    let server = Server::start();
+    let mut stream = TcpStream::connect(&server.address).unwrap();
+    stream
+        .write_all(b"DELETE / HTTP/1.1\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(
+        response.starts_with("HTTP/1.1 405 Method Not Allowed\r\nAllow: GET\r\n"),
+        "{response}"
+    );
+}
+
+#[test]
+fn malformed_request() {
+    let server = Server::start();
+    let mut stream = TcpStream::connect(&server.address).unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost localhost\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(
+        response.starts_with("HTTP/1.1 400 Bad Request\r\n"),
+        "{response}"
+    );
+
+    // The server is still there for the next client.
+    assert!(server.get("/").starts_with("HTTP/1.1 200 OK\r\n"));
+}
+
+#[test]
+fn static_files() {
+    let root = env::temp_dir().join(format!("hello-server-static-{}", process::id()));
+    fs::create_dir_all(root.join("docs")).unwrap();
+    fs::write(root.join("docs/index.html"), "<p>docs</p>").unwrap();
+    fs::write(root.join("data.json"), "[1, 2, 3]").unwrap();
+    let server = Server::start_with(&[root.to_str().unwrap()]);
+
+    let response = server.get("/static/data.json");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+    assert!(response.contains("\r\nContent-Type: application/json\r\n"));
+    assert!(response.ends_with("\r\n\r\n[1, 2, 3]"));
+    assert!(server.get("/static/docs/").ends_with("<p>docs</p>"));
+    assert!(server
+        .get("/static/../Cargo.toml")
+        .starts_with("HTTP/1.1 403 Forbidden\r\n"));
+
+    let mut stream = TcpStream::connect(&server.address).unwrap();
+    stream
+        .write_all(
+            b"GET /static/data.json HTTP/1.1\r\nRange: bytes=-2\r\nConnection: close\r\n\r\n",
+        )
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(
+        response.starts_with("HTTP/1.1 206 Partial Content\r\n"),
+        "{response}"
+    );
+    assert!(response.contains("\r\nContent-Range: bytes 7-8/9\r\n"));
+    assert!(response.ends_with("\r\n\r\n3]"));
+
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn static_files_by_default() {
+    // Served from the crate's static directory without any arguments.
+    let server = Server::start();
+    let response = server.get("/static/");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+    assert!(response.contains("<h1>Static files</h1>"));
+}
+
+#[test]
+fn keep_alive_and_pipelining() {
+    let server = Server::start();
+    let mut stream = TcpStream::connect(&server.address).unwrap();
+
+    // One request, answered on a connection that stays open.
+    stream.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+    let first = read_page(&mut stream);
+    assert!(first.starts_with("HTTP/1.1 200 OK\r\n"), "{first}");
+    assert!(first.contains("\r\nConnection: keep-alive\r\n"));
+
+    // Then three at once, answered in order, the last one closing.
+    stream
+        .write_all(
+            b"GET /nope HTTP/1.1\r\n\r\n\
+              POST / HTTP/1.1\r\nContent-Length: 2\r\n\r\nhi\
+              GET / HTTP/1.1\r\nConnection: close\r\n\r\n",
+        )
+        .unwrap();
+    let mut rest = String::new();
+    stream.read_to_string(&mut rest).unwrap();
+    let statuses: Vec<&str> = rest
+        .lines()
+        .filter(|line| line.starts_with("HTTP/1.1 "))
+        .collect();
+    assert_eq!(
+        vec![
+            "HTTP/1.1 404 Not Found",
+            "HTTP/1.1 405 Method Not Allowed",
+            "HTTP/1.1 200 OK"
+        ],
+        statuses
+    );
+    assert!(rest.ends_with("</html>\n"), "{rest}");
+    assert_eq!(1, rest.matches("Connection: close").count());
+}
+
+#[test]
+fn http_1_0_and_idle_connections_are_closed() {
+    let server = Server::start();
+    let mut stream = TcpStream::connect(&server.address).unwrap();
+    stream.write_all(b"GET / HTTP/1.0\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.contains("\r\nConnection: close\r\n"), "{response}");
+
+    // Nothing more is sent, so the server gives up after a while.
+    let started = Instant::now();
+    let mut stream = TcpStream::connect(&server.address).unwrap();
+    let mut rest = Vec::new();
+    stream.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+    assert!(started.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+fn idle_connections_make_way() {
+    let server = Server::start();
+
+    // More kept-alive connections than the pool has workers, all idle.
+    let idle: Vec<TcpStream> = (0..6)
+        .map(|_| {
+            let mut stream = TcpStream::connect(&server.address).unwrap();
+            stream.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+            read_page(&mut stream);
+            stream
+        })
+        .collect();
+
+    let started = Instant::now();
+    let response = server.get("/");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+    assert!(
+        started.elapsed() < Duration::from_secs(2),
+        "/ took {:?}",
+        started.elapsed()
+    );
+    drop(idle);
+}
+
+#[test]
+fn connections_close_after_many_requests() {
+    let server = Server::start();
+    let mut stream = TcpStream::connect(&server.address).unwrap();
+    stream
+        .write_all(b"GET /nope HTTP/1.1\r\n\r\n".repeat(150).as_slice())
+        .unwrap();
+    let mut responses = String::new();
+    stream.read_to_string(&mut responses).unwrap();
+    assert_eq!(100, responses.matches("HTTP/1.1 404 Not Found\r\n").count());
+    assert_eq!(99, responses.matches("Connection: keep-alive").count());
+    assert!(responses.contains("Connection: close"));
+}
+
+// Reads one response to a request for hello.html.
+fn read_page(stream: &mut TcpStream) -> String {
+    let mut page = Vec::new();
+    let mut buf = [0; 512];
+    while !page.ends_with(b"</html>\n") {
+        let n = stream.read(&mut buf).unwrap();
+        assert!(n > 0, "connection closed");
+        page.extend_from_slice(&buf[..n]);
+    }
+    String::from_utf8(page).unwrap()
+}